@@ -1,35 +1,117 @@
 use std::{
-    cmp::{max, min}, fmt::Display, fs::DirEntry, io::{self, ErrorKind}, path::{Path, PathBuf}, process::Stdio
+    cmp::{max, min, Ordering}, collections::{BTreeSet, HashMap, VecDeque}, fmt::Display, 
+    io::{self, ErrorKind}, path::{Path, PathBuf}, process::{ExitStatus, Stdio}, rc::Rc, sync::Arc, time::Duration
 };
 
 use crossterm::{
-    cursor::{MoveDown, MoveLeft, MoveRight, MoveTo, MoveToColumn, MoveToRow, MoveUp, RestorePosition, SavePosition},
-    event::{read, Event, KeyCode, KeyEvent, KeyModifiers},
+    event::{poll, read, Event, KeyCode, KeyEvent, KeyModifiers},
     terminal::{
-        self, Clear, ClearType, SetSize
+        self, disable_raw_mode, enable_raw_mode, 
+        DisableLineWrap, EnableLineWrap, EnterAlternateScreen, LeaveAlternateScreen, SetSize
     },
-    execute, style::{Color, ResetColor, SetForegroundColor, Stylize}
+    execute, style::Stylize
 };
-use is_executable::IsExecutable;
 use path_absolutize::Absolutize;
-use unicode_segmentation::UnicodeSegmentation;
 
-use crate::{BOTTOM_RESERVED, START_X, START_Y, Position, write_to_screen, writeln_to_screen};
+use crate::{
+    archive::{self, Archives}, color_config::ColorConfig, command_line::CommandLine, commands, desktop::DesktopEntry, du::DiskUsage, entry::{Entry, Line}, exec, file_ops, filter::Filter, 
+    loader::{Loader, Progress, Walk}, menu, openers::Openers, pager::Pager, picker::Picker, shell_init::posix_quote, settings::{ExecPolicy, Settings, SortKey}, render::{Frame, Renderer}, 
+    tab::{Pane, Tab}, tree::Tree, vfs::{Local, Vfs}, watcher::Watcher,
+    BOTTOM_RESERVED, START_X, START_Y, Position
+};
 
 pub const LEFT_SIDE_PADDING: u16 = 4;
+/// How long to wait for a directory to be read before drawing it partially.
+const LOAD_WAIT: Duration = Duration::from_millis(30);
+/// How often to check for input, and for directories that changed.
+const IDLE_POLL: Duration = Duration::from_millis(100);
+/// Same, while a directory is being read.
+const LOADING_POLL: Duration = Duration::from_millis(50);
 
 pub struct App {
-    pub buffer: Vec<(Option<PathBuf>, usize, String)>,
+    pub buffer: Vec<Line>,
+    /// Everything read from `cd` so far, before filtering and sorting.
+    pub entries: Vec<Rc<Entry>>,
+    /// Set while `cd` is being read.
+    pub loader: Option<Loader>,
+    /// Directories of the listing expanded in place.
+    pub tree: Tree,
+    /// Set while every file under `cd` is listed at once, to how many
+    /// levels down it goes.
+    pub flat: Option<usize>,
     pub cd: PathBuf,
-    pub output: String,
-    pub index: u16,
+    /// The directory fap was left in, or `None` when it was cancelled.
+    pub output: Option<PathBuf>,
+    /// Line of the buffer shown on the first row of the screen.
+    pub index: usize,
     pub stored_position: Position,
     pub cursor_position: Position,
-    pub command_state: CommandState
+    pub command_state: CommandState,
+    pub settings: Settings,
+    /// Patterns that narrow the listing down, by directory. Each one is
+    /// kept until it is cleared.
+    pub filters: HashMap<PathBuf, String>,
+    pub command_line: CommandLine,
+    /// Prompt for the arguments of programs run with `x` and `X`, with a
+    /// history of its own.
+    pub args_line: CommandLine,
+    /// Prompt for shell commands run with `!`.
+    pub shell_line: CommandLine,
+    /// Prompt for filters typed after `zf`.
+    pub filter_line: CommandLine,
+    pub mappings: HashMap<char, String>,
+    pub pending_keys: VecDeque<KeyEvent>,
+    pub status: String,
+    pub quit: bool,
+    /// Every open tab. The slot at `active_tab` is stale, since the active
+    /// tab's state is held by the `App` itself.
+    pub tabs: Vec<Tab>,
+    pub active_tab: usize,
+    pub selected: BTreeSet<PathBuf>,
+    /// The pane without focus while in commander mode.
+    pub pane: Option<Pane>,
+    /// Whether `with_other_pane` has that pane swapped in, leaving the one
+    /// with focus out of reach.
+    in_other_pane: bool,
+    pub focus_right: bool,
+    /// `None` where directories cannot be watched, in which case the
+    /// listing is only reloaded on navigation or by hand.
+    pub watcher: Option<Watcher>,
+    pub renderer: Renderer,
+    pub theme: ColorConfig,
+    pub openers: Openers,
+    /// Where directories are listed from and files changed.
+    pub vfs: Arc<dyn Vfs>,
+    /// Sizes of the listed entries in du mode, shared by every view.
+    pub du: DiskUsage,
+    /// Shown at the top of the screen instead of the first rule, e.g. to
+    /// tell what is being picked.
+    pub title: Option<String>,
+    /// Set when fap is used as a file picker.
+    pub picker: Option<Picker>,
+    /// What was picked, once the picker is accepted.
+    pub chosen: Vec<PathBuf>
+}
+
+/// The parts of a directory view needed to draw it, so that the pane
+/// without focus can be drawn the same way as the one with focus.
+struct View<'a> {
+    buffer: &'a [Line],
+    index: usize,
+    row: u16,
+    selected: &'a BTreeSet<PathBuf>,
+    header: Option<String>,
+    theme: &'a ColorConfig,
+    /// Whether `.desktop` files are followed by their application name.
+    desktop_names: bool,
+    /// Set in du mode, to follow entries by their size.
+    du: Option<&'a DiskUsage>,
+    apparent: bool,
+    dedup: bool,
 }
 
 pub struct CommandState {
-    number: Option<u16>,
+    number: Option<usize>,
     prefix: Prefix,
 }
 
@@ -47,116 +129,371 @@ pub enum Prefix {
 }
 
 impl App {
-    pub fn generate_buffer(&mut self){
+    /// Starts reading `cd` in the background. Entries show up as they
+    /// are read, so that the listing is usable before the whole directory is.
+    pub fn generate_buffer(&mut self) {
+        self.entries.clear();
+        self.load(true);
+    }
+
+    /// Reads `cd` again in the background. The current entries stay on
+    /// screen until the new ones are all in, so the cursor does not jump.
+    fn load(&mut self, progressive: bool) {
+        let with_metadata = self.settings.sort.needs_metadata();
+        self.loader = None;
+        self.tree.forget();
+        // What the walk leaves out depends on the settings at the time, so
+        // a flat listing is read again whenever they change.
+        let walk = self.flat.map(|depth| {
+            let settings = self.settings.clone();
+            Walk { depth, hides: Box::new(move |name| settings.hides(name)) }
+        });
+        let mut loader = Loader::spawn(self.vfs.clone(), self.cd.clone(), with_metadata, progressive, walk);
+        let progress = loader.wait(LOAD_WAIT);
+        self.loader = Some(loader);
+        self.receive(progress);
+    }
+
+    /// Adds what arrived from the loader to the listing, keeping the cursor
+    /// on the same entry.
+    fn receive(&mut self, progress: Progress) {
+        if let Some(e) = progress.error {
+            self.status = format!("cannot read {}: {}", self.cd.display(), e);
+        }
+        if progress.done && self.loader.take().is_some_and(|loader| !loader.progressive) {
+            self.entries.clear();
+        }
+        self.entries.extend(progress.entries.into_iter().map(Rc::new));
+        if progress.done {
+            self.du.refresh(&self.cd);
+        }
+
+        let _ = self.rearrange();
+    }
+
+    /// Takes whatever the loader read since the last call. Returns whether
+    /// the listing changed.
+    fn poll_loader(&mut self) -> bool {
+        let loader = match &mut self.loader {
+            Some(loader) => loader,
+            None => return false
+        };
+        let count = loader.count;
+        let progress = loader.poll();
+        if !progress.done && loader.count == count {
+            return false;
+        }
+        self.receive(progress);
+        true
+    }
+
+    fn is_loading(&self) -> bool {
+        self.loader.is_some()
+            || self.pane.as_ref().is_some_and(|pane| pane.view.loader.is_some())
+    }
+
+    /// Builds the buffer from the entries read so far, applying the
+    /// settings and the filter. Expanded directories are read the first
+    /// time they are shown.
+    fn build_buffer(&mut self) {
+        self.read_expanded();
+
         let mut output = vec![];
-        output.push((None, 48, format!("{empty:=<48}", empty = "")));
-        output.push((
-            None, 
-            self.cd.to_str().unwrap_or_default().len(), 
-            format!("{}", self.cd.to_str().unwrap())
-        ));
-        output.push((None, 48, format!("{empty:=<48}", empty = "")));
-        match self.cd.parent() {
-            Some(parent) => output.push((
-                Some(parent.to_path_buf()), 
-                3,
-                format!("{}..{}/{}", 
-                    SetForegroundColor(Color::Cyan), 
-                    SetForegroundColor(Color::Rgb {r: 255, g: 192, b: 203}),
-                    ResetColor
-                )
-            )),
+        let mut cd = match &self.loader {
+            Some(loader) => format!("{} (loading… {})", self.cd.display(), loader.count),
+            None => self.cd.display().to_string()
+        };
+        match self.flat {
+            Some(usize::MAX) => cd += " [flat]",
+            Some(depth) => cd += &format!(" [flat: {} levels]", depth),
             None => ()
+        }
+        let filter = self.filters.get(&self.cd).map(|pattern| {
+            cd += &format!(" [filter: {}]", pattern);
+            Filter::new(pattern, self.settings.filter_mode)
+        });
+        output.push(Line::text(match &self.title {
+            Some(title) => format!("{:=<48}", format!("{} ", title)),
+            None => format!("{empty:=<48}", empty = "")
+        }));
+        output.push(Line::text(cd));
+        output.push(Line::text(format!("{empty:=<48}", empty = "")));
+        if let Some(parent) = self.cd.parent() {
+            output.push(Line::Link { path: Some(parent.to_path_buf()), name: ".." });
+        }
+        output.push(Line::Link { path: None, name: "." });
+
+        // Recursive sizes in du mode, the length of files otherwise.
+        let size = |entry: &Entry| match self.settings.du {
+            true => self.du.size(&entry.path, self.settings.apparent, self.settings.dedup),
+            false => entry.metadata().map(|metadata| metadata.len)
         };
-        output.push((None, 2, format!("{}.{}/{}", 
-            SetForegroundColor(Color::Cyan), 
-            SetForegroundColor(Color::Rgb {r: 255, g: 192, b: 203}),
-            ResetColor
-        )));
-        let dir = &self.cd;
-        let dir_entries: Vec<DirEntry> = std::fs::read_dir(dir)
-            .unwrap().map(|e| e.unwrap()).collect();
-    
-        let (mut dirs, mut files) = (vec![], vec![]);
-        for dir_entry in dir_entries {
-            if dir_entry.metadata().unwrap().is_dir() {
-                dirs.push(dir_entry);
-            } else {
-                files.push(dir_entry);
+        output.extend(self.tree.lines(
+            &self.entries,
+            &|entry| self.is_listed(entry),
+            &|entry| filter.as_ref().is_none_or(|filter| filter.matches(&entry.name)),
+            &|a, b| {
+                let group = if self.settings.dirs_first {
+                    b.is_dir_hint().cmp(&a.is_dir_hint())
+                } else {
+                    Ordering::Equal
+                };
+                let order = compare_entries(self.settings.sort, a, b, &size);
+                group.then(if self.settings.reverse { order.reverse() } else { order })
+            }
+        ));
+
+        self.buffer = output;
+    }
+
+    /// Whether `entry` is listed at all, filter aside. Entries of a flat
+    /// listing are hidden when any directory on their way is.
+    fn is_listed(&self, entry: &Entry) -> bool {
+        !entry.name.split('/').any(|name| self.settings.hides(name))
+            && self.picker.as_ref().is_none_or(|picker| picker.shows(entry))
+    }
+
+    /// Lists every file `depth` levels down from `cd` at once, or goes back
+    /// to listing `cd` alone when there is no depth.
+    pub fn flatten(&mut self, depth: Option<usize>) -> io::Result<()> {
+        self.flat = depth;
+        self.generate_buffer();
+        self.draw_screen()?;
+
+        Ok(())
+    }
+
+    /// Reads the expanded directories that are shown and were not read yet.
+    fn read_expanded(&mut self) {
+        let with_metadata = self.settings.sort.needs_metadata();
+        for dir in self.tree.shown(&self.cd) {
+            if let Err(e) = self.tree.read(&self.vfs, &dir, with_metadata) {
+                self.status = format!("cannot read {}: {}", dir.display(), e);
             }
-        } 
-    
-        dirs.sort_by_key(|dir| dir.path());
-        files.sort_by_key(|dir| dir.path());
-        
-        for path in dirs {
-            output.push((
-                Some(path.path()), 
-                path.file_name().to_str().unwrap_or_default().graphemes(true).count() + 1,
-                format!("{}{}", path.file_name().to_str().unwrap().stylize().cyan(), "/".stylize().red())
-            ));
         }
-        for path in files {
-            output.push((
-                Some(path.path()), 
-                path.file_name().to_str().unwrap_or_default().graphemes(true).count(),
-                path.file_name().to_str().unwrap_or_default().to_string()
-            ));
+    }
+
+    /// Expands the directory under the cursor in place.
+    pub fn expand_dir(&mut self) -> io::Result<()> {
+        match self.current_entry() {
+            Some(entry) if entry.is_dir() => self.tree.expand(entry.path.clone()),
+            _ => return Ok(())
         }
-    
-        self.buffer = output;
+        self.rearrange()
     }
 
-    pub fn draw_screen(&self) -> io::Result<()> {
-        let (_col, row) = self.cursor_position.get();
-        execute!(io::stderr(), Clear(ClearType::All), MoveTo(0, 0))?;
-    
-        let rows = terminal::window_size()?.rows;
-    
-        for i in 0..(rows - BOTTOM_RESERVED) {
-            if self.index + i >= self.buffer.len() as u16 {
-                writeln_to_screen(format!("~"))?;
-                continue;
+    /// Collapses the directory under the cursor, or the one it is listed
+    /// under, moving the cursor onto it.
+    pub fn collapse_dir(&mut self) -> io::Result<()> {
+        let path = match self.current_entry() {
+            Some(entry) => entry.path.clone(),
+            None => return Ok(())
+        };
+        if !self.tree.collapse(&path) {
+            match path.parent() {
+                Some(parent) if parent != self.cd => self.tree.collapse(parent),
+                _ => return Ok(())
+            };
+        }
+        self.rearrange()
+    }
+
+    /// Expands the directory under the cursor, or collapses it when it
+    /// already is. On a file, collapses the directory it is listed under.
+    pub fn toggle_dir(&mut self) -> io::Result<()> {
+        match self.current_entry() {
+            Some(entry) if entry.is_dir() && !self.tree.is_expanded(&entry.path) => self.expand_dir(),
+            _ => self.collapse_dir()
+        }
+    }
+
+    /// Expands every directory listed down to `depth` levels under `cd`.
+    /// Hidden entries are left alone, but not the ones the filter hides,
+    /// so that it can find what is deeper down.
+    pub fn expand_all(&mut self, depth: usize) -> io::Result<()> {
+        let with_metadata = self.settings.sort.needs_metadata();
+        let mut level = self.entries.clone();
+        for _ in 0..depth {
+            let mut next = vec![];
+            for entry in level {
+                if !self.is_listed(&entry) || !entry.is_dir() {
+                    continue;
+                }
+                self.tree.expand(entry.path.clone());
+                if let Err(e) = self.tree.read(&self.vfs, &entry.path, with_metadata) {
+                    self.status = format!("cannot read {}: {}", entry.path.display(), e);
+                }
+                next.extend(self.tree.children(&entry.path).unwrap_or_default().iter().cloned());
             }
-            
-            let (_, _, display) = &self.buffer[(self.index + i) as usize];
-            let distance = (row as i16 - i as i16).abs() as u16;
-            write_to_screen(format!("{distance:>3} "))?;
-            writeln_to_screen(format!("{}", display))?;
+            level = next;
+        }
+        self.rearrange()
+    }
+
+    /// Collapses every directory under `cd` but the ones up to `depth`
+    /// levels down, the way `expand_all` leaves them.
+    pub fn collapse_all(&mut self, depth: usize) -> io::Result<()> {
+        self.tree.collapse_below(&self.cd.clone(), depth);
+        self.rearrange()
+    }
+
+    /// Sets the filter of `cd`, clearing it when there is no pattern.
+    pub fn set_filter(&mut self, pattern: Option<String>) {
+        match pattern {
+            Some(pattern) if !pattern.is_empty() => self.filters.insert(self.cd.clone(), pattern),
+            _ => self.filters.remove(&self.cd)
+        };
+    }
+
+    /// Narrows the listing down while a filter is typed. Enter keeps the
+    /// filter, Esc puts the previous one back.
+    pub fn live_filter(&mut self) -> io::Result<()> {
+        let previous = self.filters.get(&self.cd).cloned();
+        let mut filter_line = std::mem::take(&mut self.filter_line);
+        let line = filter_line.read_line_live("filter: ", previous.as_deref().unwrap_or(""), &mut |pattern| {
+            self.set_filter(Some(pattern.to_string()));
+            self.rearrange()?;
+            self.draw_screen()
+        });
+        self.filter_line = filter_line;
+        self.renderer.invalidate();
+
+        match line? {
+            Some(pattern) => {
+                self.filter_line.push_history(&pattern);
+                self.set_filter(Some(pattern));
+            },
+            None => self.set_filter(previous)
+        }
+        self.rearrange()?;
+        self.draw_screen()?;
+
+        Ok(())
+    }
+
+    /// Draws the whole screen into a frame, and writes whatever changed
+    /// since the last one to the terminal.
+    pub fn draw_screen(&mut self) -> io::Result<()> {
+        let (frame, cursor) = self.frame()?;
+        self.renderer.render(frame, cursor)?;
+
+        Ok(())
+    }
+
+    /// Draws the whole screen into a new frame. Returns it alongside where
+    /// the cursor goes.
+    fn frame(&mut self) -> io::Result<(Frame, (u16, u16))> {
+        let mut frame = Frame::for_terminal()?;
+        let columns = frame.width();
+
+        let view = View {
+            buffer: &self.buffer,
+            index: self.index,
+            row: self.cursor_position.row(),
+            selected: &self.selected,
+            header: (self.tabs.len() > 1).then(|| tab_bar(&self.tabs, self.active_tab, &self.cd)),
+            theme: &self.theme,
+            desktop_names: self.settings.desktop_names,
+            du: self.settings.du.then_some(&self.du),
+            apparent: self.settings.apparent,
+            dedup: self.settings.dedup,
+        };
+
+        match &self.pane {
+            Some(pane) => {
+                let half = columns / 2;
+                let other = View {
+                    buffer: &pane.view.buffer,
+                    index: pane.view.index,
+                    row: pane.view.cursor_position.row(),
+                    selected: &pane.view.selected,
+                    header: (pane.tabs.len() > 1)
+                        .then(|| tab_bar(&pane.tabs, pane.active_tab, &pane.view.cd)),
+                    theme: &self.theme,
+                    desktop_names: self.settings.desktop_names,
+                    du: self.settings.du.then_some(&self.du),
+                    apparent: self.settings.apparent,
+                    dedup: self.settings.dedup,
+                };
+                let (left, right) = if self.focus_right { (other, view) } else { (view, other) };
+                draw_view(&mut frame, left, 0, half.saturating_sub(1));
+                draw_divider(&mut frame, half.saturating_sub(1));
+                draw_view(&mut frame, right, half, columns - half);
+            },
+            None => draw_view(&mut frame, view, 0, columns)
         }
     
-        self.write_bottom()?;
+        self.draw_bottom(&mut frame);
+
+        let cursor = (
+            self.left_padding()? + self.cursor_position.col(), 
+            self.cursor_position.row()
+        );
     
-        return Ok(());
+        Ok((frame, cursor))
+    }
+
+    /// Shows a menu of `items` until one is picked with Enter or its number,
+    /// or the menu is dismissed with Esc or q.
+    pub fn menu(&mut self, title: &str, items: &[String]) -> io::Result<Option<usize>> {
+        let mut selected = 0;
+        loop {
+            let (mut frame, cursor) = self.frame()?;
+            menu::draw_menu(&mut frame, title, items, selected);
+            self.renderer.render(frame, cursor)?;
+
+            let code = match read()? {
+                Event::Key(KeyEvent{code, ..}) => code,
+                _ => continue
+            };
+            match code {
+                KeyCode::Char('j') | KeyCode::Down => selected = min(selected + 1, items.len() - 1),
+                KeyCode::Char('k') | KeyCode::Up => selected = selected.saturating_sub(1),
+                KeyCode::Enter => return Ok(Some(selected)),
+                KeyCode::Esc | KeyCode::Char('q') => return Ok(None),
+                KeyCode::Char(c) => match c.to_digit(10) {
+                    Some(n) if n >= 1 && n as usize <= items.len() => return Ok(Some(n as usize - 1)),
+                    _ => ()
+                },
+                _ => ()
+            }
+        }
     }
 
-    pub fn write_bottom(&self) -> io::Result<()> {
-        let rows = terminal::window_size()?.rows;
+    /// Column where the names of the focused view start.
+    pub fn left_padding(&self) -> io::Result<u16> {
+        if self.pane.is_some() && self.focus_right {
+            return Ok(terminal::window_size()?.columns / 2 + LEFT_SIDE_PADDING);
+        }
+        Ok(LEFT_SIDE_PADDING)
+    }
+
+    fn draw_bottom(&self, frame: &mut Frame) {
+        let (width, rows) = (frame.width(), frame.height());
     
-        let (_, len, _) = &self.buffer[(self.index + self.cursor_position.row()) as usize];
+        let len = self.buffer[self.current_line()].len();
         
-        execute!(io::stderr(), MoveTo(0, rows - BOTTOM_RESERVED))?;
-        writeln_to_screen(format!("{empty:=<24}", empty = ""))?;
-        writeln_to_screen(format!(
-            "index: {}, x: {}, y: {}. items: {}, cur item len: {} {empty: <8}", 
+        frame.put_str(0, rows.saturating_sub(BOTTOM_RESERVED), &format!("{empty:=<24}", empty = ""), width);
+        frame.put_str(0, rows.saturating_sub(BOTTOM_RESERVED - 1), &format!(
+            "index: {}, x: {}, y: {}. items: {}, cur item len: {}", 
             self.index,
             self.cursor_position.col(), 
             self.cursor_position.row(), 
             self.buffer.len(),
-            len,
-            empty = ""
-        ))?;
-        write_to_screen(format!("{}", self.command_state))?;
-    
-        return Ok(());
+            len
+        ), width);
+        frame.put_str(0, rows.saturating_sub(1), &format!("{}{}", self.command_state, self.status), width);
     }
 
     pub fn read_input(&mut self) -> io::Result<()> {
         self.cursor_position = Position::new(START_X, START_Y);
         self.stored_position = Position::new(START_X, START_Y);
+        self.watch_dirs();
         loop {
-            match read()? {
-                Event::Key(KeyEvent{code: KeyCode::Char(n), ..}) if n.is_digit(10) => {
+            let event = self.next_event()?;
+            self.status.clear();
+            match event {
+                Event::Key(KeyEvent{code: KeyCode::Char(n), ..}) if n.is_ascii_digit() => {
                     if n == '0' && self.command_state.number.is_none() {
 
                         continue;
@@ -173,6 +510,26 @@ impl App {
                         self.command_state.prefix = Prefix::g;
                     }
                 },
+                Event::Key(KeyEvent{code: KeyCode::Char('t'), ..}) => {
+                    if self.command_state.prefix == Prefix::g {
+                        match self.command_state.number.take() {
+                            Some(n) => self.switch_tab(n - 1)?,
+                            None => self.switch_tab((self.active_tab + 1) % self.tabs.len())?
+                        }
+                    }
+                    self.command_state.prefix = Prefix::None;
+                },
+                Event::Key(KeyEvent{code: KeyCode::Char('T'), ..}) => {
+                    if self.command_state.prefix == Prefix::g {
+                        let times = self.command_state.number.take().unwrap_or(1);
+                        let len = self.tabs.len();
+                        self.switch_tab((self.active_tab + len - times % len) % len)?;
+                    }
+                    self.command_state.prefix = Prefix::None;
+                },
+                Event::Key(KeyEvent{code: KeyCode::Char('z'), ..}) => {
+                    self.command_state.prefix = Prefix::z;
+                },
                 Event::Key(KeyEvent{
                     code: KeyCode::Char('e'), 
                     modifiers: KeyModifiers::CONTROL, ..
@@ -200,6 +557,30 @@ impl App {
                     )?;
                     self.command_state.prefix = Prefix::None;
                 },
+                Event::Key(KeyEvent{code: KeyCode::Char('f'), ..}) => {
+                    if self.command_state.prefix == Prefix::z {
+                        self.live_filter()?;
+                    }
+                    self.command_state = CommandState::default();
+                },
+                Event::Key(KeyEvent{code: KeyCode::Char('o'), ..}) => {
+                    if self.command_state.prefix == Prefix::z {
+                        self.expand_dir()?;
+                    }
+                    self.command_state = CommandState::default();
+                },
+                Event::Key(KeyEvent{code: KeyCode::Char('c'), ..}) => {
+                    if self.command_state.prefix == Prefix::z {
+                        self.collapse_dir()?;
+                    }
+                    self.command_state = CommandState::default();
+                },
+                Event::Key(KeyEvent{code: KeyCode::Char('a'), ..}) => {
+                    if self.command_state.prefix == Prefix::z {
+                        self.toggle_dir()?;
+                    }
+                    self.command_state = CommandState::default();
+                },
                 Event::Key(KeyEvent{
                     code: KeyCode::Char('b'), 
                     modifiers: KeyModifiers::CONTROL, ..
@@ -235,6 +616,10 @@ impl App {
                     self.move_cursor_to_top()?;
                     self.command_state.prefix = Prefix::None;
                 },
+                Event::Key(KeyEvent{code: KeyCode::Char('M'), ..}) if self.command_state.prefix == Prefix::z => {
+                    self.collapse_all(self.command_state.number.unwrap_or(0))?;
+                    self.command_state = CommandState::default();
+                },
                 Event::Key(KeyEvent{code: KeyCode::Char('M'), ..}) => {
                     self.move_cursor_to_middle()?;
                     self.command_state.prefix = Prefix::None;
@@ -261,6 +646,58 @@ impl App {
                     )?;
                     self.command_state.prefix = Prefix::None;
                 },
+                Event::Key(KeyEvent{code: KeyCode::Char('R'), ..}) if self.command_state.prefix == Prefix::z => {
+                    let depth = self.command_state.number.unwrap_or(self.settings.tree_depth);
+                    self.expand_all(depth)?;
+                    self.command_state = CommandState::default();
+                },
+                Event::Key(KeyEvent{
+                    code: KeyCode::Char('l'), 
+                    modifiers: KeyModifiers::CONTROL, ..
+                })
+                | Event::Key(KeyEvent{code: KeyCode::Char('R'), ..}) => {
+                    self.du.clear_cache();
+                    self.reload_from_disk()?;
+                    self.with_other_pane(|app| app.reload_from_disk()).transpose()?;
+                    self.draw_screen()?;
+                    self.command_state = CommandState::default();
+                },
+                Event::Key(KeyEvent{code: KeyCode::Char('O'), ..}) => {
+                    self.open_with_menu()?;
+                    self.command_state = CommandState::default();
+                },
+                Event::Key(KeyEvent{code: KeyCode::Char('e'), ..}) => {
+                    self.open_in("EDITOR", "vi")?;
+                    self.command_state = CommandState::default();
+                },
+                Event::Key(KeyEvent{code: KeyCode::Char('i'), ..}) => {
+                    self.open_in("PAGER", "less")?;
+                    self.command_state = CommandState::default();
+                },
+                Event::Key(KeyEvent{code: KeyCode::Char('x'), ..}) => {
+                    self.run_with_args(false)?;
+                    self.command_state = CommandState::default();
+                },
+                Event::Key(KeyEvent{code: KeyCode::Char('X'), ..}) => {
+                    self.run_with_args(true)?;
+                    self.command_state = CommandState::default();
+                },
+                Event::Key(KeyEvent{code: KeyCode::Char('!'), ..}) => {
+                    self.enter_shell_command()?;
+                    self.command_state = CommandState::default();
+                },
+                Event::Key(KeyEvent{code: KeyCode::Char('S'), ..}) => {
+                    self.subshell()?;
+                    self.command_state = CommandState::default();
+                },
+                Event::Key(KeyEvent{code: KeyCode::Char('E'), ..}) => {
+                    self.prompt_path("extract to ", |app, dest| app.extract((!dest.is_empty()).then_some(dest)))?;
+                    self.command_state = CommandState::default();
+                },
+                Event::Key(KeyEvent{code: KeyCode::Char('C'), ..}) => {
+                    self.prompt_path("archive name ", |app, name| app.compress(name))?;
+                    self.command_state = CommandState::default();
+                },
                 Event::Key(KeyEvent{code: KeyCode::Char('l'), ..}) => {
                     self.loop_fn(
                         |s| s.move_cursor_right()
@@ -268,20 +705,55 @@ impl App {
                     self.command_state.prefix = Prefix::None;
                 },
                 Event::Key(KeyEvent{code: KeyCode::Char('-'), ..}) => {
-                    self.go_to_parent_dir()?;
+                    if let Err(e) = self.go_to_parent_dir() {
+                        self.status = e.to_string();
+                    }
+                },
+                Event::Key(KeyEvent{code: KeyCode::Char(' '), ..}) if self.picker.is_some() => {
+                    self.choose();
                 },
                 Event::Key(KeyEvent{code: KeyCode::Char(' '), ..}) => {
-                    self.output = self.cd.to_str().ok_or(
-                        io::Error::new(ErrorKind::InvalidData, "cannot parse cd")
-                    )?.to_string();
+                    self.output = Some(self.cd.clone());
                     break;
                 },
                 Event::Key(KeyEvent{code: KeyCode::Enter, ..}) => {
-                    self.select_entry()?;
+                    if let Err(e) = self.select_entry() {
+                        self.status = e.to_string();
+                    }
+                    self.command_state.prefix = Prefix::None;
+                },
+                Event::Key(KeyEvent{code: KeyCode::Char('V'), ..}) => {
+                    self.toggle_all_selected()?;
+                    self.command_state = CommandState::default();
+                },
+                Event::Key(KeyEvent{code: KeyCode::Char('v'), ..}) => {
+                    self.loop_fn(
+                        |s| s.toggle_selected()
+                    )?;
                     self.command_state.prefix = Prefix::None;
                 },
+                Event::Key(KeyEvent{code: KeyCode::Tab, ..}) => {
+                    self.switch_pane()?;
+                    self.command_state = CommandState::default();
+                },
+                Event::Key(KeyEvent{code: KeyCode::Char('='), ..}) => {
+                    self.sync_other_pane()?;
+                    self.command_state = CommandState::default();
+                },
+                Event::Key(KeyEvent{code: KeyCode::F(5), ..}) => {
+                    self.run_command("copy");
+                    self.command_state = CommandState::default();
+                },
+                Event::Key(KeyEvent{code: KeyCode::F(6), ..}) => {
+                    self.run_command("move");
+                    self.command_state = CommandState::default();
+                },
+                Event::Key(KeyEvent{code: KeyCode::Char(':'), ..}) => {
+                    self.enter_command_mode()?;
+                    self.command_state = CommandState::default();
+                },
                 Event::Key(KeyEvent{code: KeyCode::Esc, ..}) => {
-                    self.cd = Path::new("./").to_path_buf();
+                    self.cancel();
                     break;
                 },
                 Event::Resize(width, height) => {
@@ -289,472 +761,1361 @@ impl App {
                 }
                 _ => ()
             }
-            execute!(io::stderr(), SavePosition)?;
-            self.write_bottom()?;
-            execute!(io::stderr(), RestorePosition)?;
+            if self.quit {
+                break;
+            }
+            self.watch_dirs();
+            self.draw_screen()?;
         }
     
-        return Ok(());
+        Ok(())
     }
 
-    pub fn loop_fn(
-        &mut self, 
-        fun: fn(&mut Self) -> io::Result<()>
-    ) -> io::Result<()> {
-        let times = self.command_state.number.unwrap_or(1);
-        for _ in 0..times {
-            fun(self)?;
-        }
-        self.command_state.number = None;
+    /// Returns the next event, taking keys queued by a mapping first. Keys
+    /// produced by a mapping are never remapped.
+    fn next_event(&mut self) -> io::Result<Event> {
+        loop {
+            if let Some(key) = self.pending_keys.pop_front() {
+                return Ok(Event::Key(key));
+            }
 
-        return Ok(());
-    }
+            let timeout = if self.is_loading() || self.du.is_busy() { LOADING_POLL } else { IDLE_POLL };
+            if !poll(timeout)? {
+                self.receive_loaded()?;
+                self.receive_sizes()?;
+                self.reload_changed_dirs()?;
+                continue;
+            }
 
-    pub fn go_to_parent_dir(&mut self) -> io::Result<()> {
-        if let Some(root) = self.cd.parent() {
-            self.cd = root.to_path_buf();
-            self.generate_buffer();
-            execute!(io::stderr(), 
-                Clear(ClearType::All), 
-                MoveTo(LEFT_SIDE_PADDING,0)
-            )?;
-            self.index = 0;
-            self.cursor_position = Position::new(START_X, START_Y);
-            self.draw_screen()?;
-            execute!(io::stderr(), MoveTo(LEFT_SIDE_PADDING + START_X, START_Y))?;
-            self.stored_position = Position::new(START_X, START_Y);
+            let event = read()?;
+            if let Event::Key(KeyEvent{code: KeyCode::Char(c), modifiers, ..}) = event {
+                if !modifiers.contains(KeyModifiers::CONTROL) {
+                    if let Some(keys) = self.mappings.get(&c) {
+                        self.pending_keys.extend(commands::parse_keys(keys));
+                        continue;
+                    }
+                }
+            }
+
+            return Ok(event);
         }
-        return Ok(());
     }
-    
-    pub fn move_cursor_to_first_line(&mut self) -> io::Result<()> {
-        let (col, _) = self.cursor_position.get();
-    
-        let (_, len, _) = self.buffer[0];
-        let max_col = max(len - 1, 0) as u16;
-        
-        execute!(io::stderr(), MoveToRow(0))?;
-        self.index = 0;
-        self.stored_position.set_row(0);
-        self.cursor_position.set_row(0);
-    
-        if max_col < col {
-            execute!(io::stderr(), MoveToColumn(LEFT_SIDE_PADDING + max_col))?;
-            self.cursor_position.set_col(max_col);
-        } else if col < min(self.stored_position.col(), max_col) {
-            let new_col = min(self.stored_position.col(), max_col);
-            execute!(io::stderr(), MoveToColumn(LEFT_SIDE_PADDING + new_col))?;
-            self.cursor_position.set_col(new_col);
+
+    /// Redraws the views whose loader has read more entries.
+    fn receive_loaded(&mut self) -> io::Result<()> {
+        let changed = self.poll_loader();
+        let other_changed = self.with_other_pane(|app| app.poll_loader()).unwrap_or(false);
+        if changed || other_changed {
+            self.draw_screen()?;
         }
-        execute!(io::stderr(), SavePosition)?;
-        self.draw_screen()?;
-        execute!(io::stderr(), RestorePosition)?;
-    
-        return Ok(());
+
+        Ok(())
     }
-    
-    pub fn move_cursor_to_last_line(&mut self) -> io::Result<()> {
-        let (col, _) = self.cursor_position.get();
-        let height = terminal::window_size()?.rows - BOTTOM_RESERVED - 1;
-    
-        if (self.buffer.len() as u16) < height {
-            self.index = 0;
-            self.stored_position.set_row((self.buffer.len() - 1) as u16);
-        } else {
-            self.index = (self.buffer.len() as u16) - height - 1;
-            self.stored_position.set_row(height);
+
+    /// Redraws with the sizes measured in du mode since the last call,
+    /// sorting again when sorted by size.
+    fn receive_sizes(&mut self) -> io::Result<()> {
+        if !self.du.poll() {
+            return Ok(());
         }
-    
-        let (_, len, _) = self.buffer[self.buffer.len() - 1];
-        let max_col = max(len - 1, 0) as u16;
-        
-        execute!(io::stderr(), MoveToRow(self.stored_position.row()))?;
-        self.cursor_position.set_row(self.stored_position.row());
-    
-        if max_col < col {
-            execute!(io::stderr(), MoveToColumn(LEFT_SIDE_PADDING + max_col))?;
-            self.cursor_position.set_col(max_col);
-        } else if col < min(self.stored_position.col(), max_col) {
-            let new_col = min(self.stored_position.col(), max_col);
-            execute!(io::stderr(), MoveToColumn(LEFT_SIDE_PADDING + new_col))?;
-            self.cursor_position.set_col(new_col);
+        if self.settings.sort == SortKey::Size {
+            self.rearrange()?;
+            self.with_other_pane(|app| app.rearrange()).transpose()?;
         }
-        execute!(io::stderr(), SavePosition)?;
-        self.draw_screen()?;
-        execute!(io::stderr(), RestorePosition)?;
-    
-        return Ok(());
+
+        self.draw_screen()
     }
 
-    pub fn move_screen_down_line(&mut self) -> io::Result<()> {
-        let (_col, row) = self.cursor_position.get();
-        let height = terminal::window_size()?.rows - BOTTOM_RESERVED - 1;
+    /// Points the watcher at the directories currently on screen.
+    fn watch_dirs(&mut self) {
+        let other = self.other_pane_dir().cloned();
+        if let Some(watcher) = &mut self.watcher {
+            let mut dirs = vec![self.cd.as_path()];
+            dirs.extend(other.as_deref());
+            watcher.sync(&dirs);
+        }
+    }
 
-        if self.buffer.len() as u16 <= height || self.index >= self.buffer.len() as u16 - 1 {
+    /// Reloads whichever panes show a directory that changed on disk.
+    fn reload_changed_dirs(&mut self) -> io::Result<()> {
+        let changed = match &mut self.watcher {
+            Some(watcher) => watcher.poll(),
+            None => return Ok(())
+        };
+        if changed.is_empty() {
             return Ok(());
         }
 
-        self.index += 1;
-
-        if self.index + row == self.buffer.len() as u16 {
-            execute!(io::stderr(), MoveUp(1))?;
-            self.cursor_position.move_up();
-            self.stored_position.move_up();
+        if changed.contains(&self.cd) {
+            self.reload_from_disk()?;
         }
+        if self.other_pane_dir().is_some_and(|dir| changed.contains(dir)) {
+            self.with_other_pane(|app| app.reload_from_disk()).transpose()?;
+        }
+        self.watch_dirs();
 
-        execute!(io::stderr(), SavePosition)?;
         self.draw_screen()?;
-        execute!(io::stderr(), RestorePosition)?;
 
-        return Ok(());
+        Ok(())
     }
-    
-    pub fn move_screen_up_line(&mut self) -> io::Result<()> {
-        let height = terminal::window_size()?.rows - BOTTOM_RESERVED - 1;
 
-        if self.buffer.len() as u16 <= height || self.index == 0 {
-            return Ok(());
+    /// Reloads the listing, moving up to the closest directory that still
+    /// exists if `cd` was removed.
+    pub fn reload_from_disk(&mut self) -> io::Result<()> {
+        let exists = |dir: &Path| archive::split(self.vfs.as_ref(), dir).is_some() || self.vfs.list(dir).is_ok();
+        if exists(&self.cd) {
+            return self.reload();
         }
 
-        self.index -= 1;
-
-        execute!(io::stderr(), SavePosition)?;
-        self.draw_screen()?;
-        execute!(io::stderr(), RestorePosition)?;
+        if let Some(dir) = self.cd.ancestors().find(|dir| exists(dir)) {
+            self.cd = dir.to_path_buf();
+        }
+        self.generate_buffer();
+        self.index = 0;
+        self.cursor_position = Position::new(START_X, START_Y);
+        self.stored_position = Position::new(START_X, START_Y);
 
-        return Ok(());
+        Ok(())
     }
 
-    pub fn move_screen_down_page(&mut self) -> io::Result<()> {
-        let height = terminal::window_size()?.rows - BOTTOM_RESERVED - 1;
-        const PAGE_GAP: u16 = 2;
-        
-        self.index = if self.index + height - PAGE_GAP > (self.buffer.len() - 1) as u16 {
-            (self.buffer.len() - 1) as u16
+    /// Reads an ex command at the `:` prompt and runs it. When a mapping has
+    /// queued keys, the command is taken from those instead of the terminal.
+    pub fn enter_command_mode(&mut self) -> io::Result<()> {
+        let line = if self.pending_keys.is_empty() {
+            let mut command_line = std::mem::take(&mut self.command_line);
+            let line = command_line.read_line(":", &|before| commands::complete(self, before));
+            self.command_line = command_line;
+            self.renderer.invalidate();
+            line?
         } else {
-            self.index + height - PAGE_GAP
+            let mut line = String::new();
+            while let Some(key) = self.pending_keys.pop_front() {
+                match key.code {
+                    KeyCode::Enter => break,
+                    KeyCode::Char(c) => line.push(c),
+                    _ => ()
+                }
+            }
+            Some(line)
         };
-        
-        execute!(io::stderr(), MoveToRow(0))?;
-        self.cursor_position.set_row(0);
-        self.stored_position.set_row(0);
 
-        execute!(io::stderr(), SavePosition)?;
+        if let Some(line) = line {
+            self.run_command(&line);
+        }
+        if self.quit {
+            return Ok(());
+        }
+
         self.draw_screen()?;
-        execute!(io::stderr(), RestorePosition)?;
 
-        return Ok(());
+        Ok(())
     }
 
-    pub fn move_screen_up_page(&mut self) -> io::Result<()> {
-        let height = terminal::window_size()?.rows - BOTTOM_RESERVED - 1;
-        const PAGE_GAP: u16 = 2;
-
-        self.index = if self.index < height - PAGE_GAP {
-            0
-        } else {
-            self.index + PAGE_GAP - height 
-        };
+    /// Asks for a shell command, then runs it with `run_shell`.
+    pub fn enter_shell_command(&mut self) -> io::Result<()> {
+        let mut shell_line = std::mem::take(&mut self.shell_line);
+        let line = shell_line.read_line("!", &|before| commands::complete_args(self, before));
+        self.shell_line = shell_line;
+        self.renderer.invalidate();
 
-        let row = min(
-            self.buffer.len() as u16 - self.index - 1, 
-            height
-        );
-        
-        execute!(io::stderr(), MoveToRow(row))?;
-        self.cursor_position.set_row(row);
-        self.stored_position.set_row(row);
+        if let Some(line) = line? {
+            self.run_shell(&line)?;
+        }
 
-        execute!(io::stderr(), SavePosition)?;
-        self.draw_screen()?;
-        execute!(io::stderr(), RestorePosition)?;
-        
-        return Ok(());
+        Ok(())
     }
 
-    pub fn move_down_half_page(&mut self) -> io::Result<()> {
-        let (_col, mut row) = self.cursor_position.get();
-        let height = terminal::window_size()?.rows - BOTTOM_RESERVED - 1;
-        
-        self.index = if 
-            ((self.buffer.len() - 1) as u16) <= height 
-            || self.index == self.buffer.len() as u16 - height - 1 
-        {
-            row = *[
-                self.buffer.len() as u16 - self.index - 1,
-                height,
-                row + height / 2 + 1, 
-            ].iter().min().unwrap_or(&row);
+    /// Runs `command` with `sh` from `cd`, showing its output in a pager.
+    /// `%f` stands for the entry under the cursor, `%s` for the marked
+    /// entries (or the one under the cursor) and `%d` for `cd`. The same
+    /// paths are exported as `$f`, `$fs` (one per line) and `$PWD`.
+    pub fn run_shell(&mut self, command: &str) -> io::Result<()> {
+        if command.trim().is_empty() {
+            return Ok(());
+        }
 
-            execute!(io::stderr(), MoveToRow(row))?;
-            self.cursor_position.set_row(row);
-            self.stored_position.set_row(row);
+        let current = self.current_path().cloned();
+        let targets = self.targets();
+        let expanded = commands::expand_placeholders(command, current.as_deref(), &targets, &self.cd);
+        let fs: Vec<String> = targets.iter().map(|path| path.display().to_string()).collect();
 
-            self.index
-        } else if self.index + height / 2 + 1 > self.buffer.len() as u16 - height - 1 {
-            self.buffer.len() as u16 - height - 1
-        } else {
-            self.index + height / 2 + 1
-        };
+        let mut shell = std::process::Command::new("sh");
+        shell.arg("-c")
+            .arg(expanded)
+            .current_dir(self.work_dir())
+            .env("f", current.unwrap_or_default())
+            .env("fs", fs.join("\n"))
+            .env("PWD", self.work_dir());
 
-        execute!(io::stderr(), SavePosition)?;
-        self.draw_screen()?;
-        execute!(io::stderr(), RestorePosition)?;
+        let title = format!("!{}", command);
+        match Pager::spawn(title.clone(), &mut shell) {
+            Ok(pager) => self.show_pager(pager)?,
+            Err(e) => self.status = format!("{}: {}", title, e)
+        }
 
-        return Ok(());
+        Ok(())
     }
 
-    pub fn move_up_half_page(&mut self) -> io::Result<()> {
-        let (_col, mut row) = self.cursor_position.get();
-        let height = terminal::window_size()?.rows - BOTTOM_RESERVED - 1;
-        
-        self.index = if self.index == 0 {
-            row -= min(row, height / 2 + 1);
+    /// Hands the terminal over to `$SHELL` started in `cd`, until it exits.
+    /// `$FAP_LEVEL` tells how many fap subshells deep the shell is, so that
+    /// prompts can show it.
+    pub fn subshell(&mut self) -> io::Result<()> {
+        let shell = std::env::var("SHELL")
+            .ok()
+            .filter(|shell| !shell.is_empty())
+            .unwrap_or_else(|| "sh".to_string());
+        let level = std::env::var("FAP_LEVEL")
+            .ok()
+            .and_then(|level| level.parse::<u32>().ok())
+            .unwrap_or(0);
 
-            execute!(io::stderr(), MoveToRow(row))?;
-            self.cursor_position.set_row(row);
-            self.stored_position.set_row(row);
+        let mut command = std::process::Command::new(&shell);
+        command.current_dir(self.work_dir())
+            .env("PWD", self.work_dir())
+            .env("FAP_LEVEL", (level + 1).to_string());
+
+        if let Err(e) = self.run_foreground(&mut command) {
+            self.status = format!("{}: {}", shell, e);
+        }
+
+        Ok(())
+    }
+
+    /// Runs an ex command, reporting any error in the status line.
+    pub fn run_command(&mut self, line: &str) {
+        if let Err(e) = commands::execute(self, line) {
+            self.status = e.to_string();
+        }
+    }
+
+    /// Shows the tab at `i`, storing the current view in its slot.
+    pub fn switch_tab(&mut self, i: usize) -> io::Result<()> {
+        if i >= self.tabs.len() {
+            self.status = format!("no tab {}", i + 1);
+            return Ok(());
+        }
+        if i != self.active_tab {
+            let mut tab = std::mem::replace(&mut self.tabs[i], Tab::new(PathBuf::new()));
+            tab.swap(self);
+            self.tabs[i] = tab;
+            self.tabs.swap(i, self.active_tab);
+            self.active_tab = i;
+        }
+
+        if self.buffer.is_empty() {
+            self.generate_buffer();
+        }
+        self.measure();
+        self.draw_screen()?;
+
+        Ok(())
+    }
+
+    /// Opens a new tab at `cd` right after the current one.
+    pub fn new_tab(&mut self, cd: PathBuf) -> io::Result<()> {
+        self.check_dir(&cd)?;
+        let cd = cd.absolutize()?.to_path_buf();
+        self.tabs.insert(self.active_tab + 1, Tab::new(cd));
+        self.switch_tab(self.active_tab + 1)
+    }
+
+    pub fn close_tab(&mut self) -> io::Result<()> {
+        if self.tabs.len() == 1 {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "cannot close the last tab"));
+        }
+
+        self.tabs.remove(self.active_tab);
+        self.active_tab = min(self.active_tab, self.tabs.len() - 1);
+        let mut tab = std::mem::replace(&mut self.tabs[self.active_tab], Tab::new(PathBuf::new()));
+        tab.swap(self);
+
+        self.switch_tab(self.active_tab)
+    }
+
+    /// Leaves fap without changing the directory of the calling shell.
+    pub fn cancel(&mut self) {
+        self.output = None;
+        self.quit = true;
+    }
+
+    /// Moves the cursor onto the line of the buffer at `line`, scrolling
+    /// only if it is not already on screen.
+    pub fn jump_to_line(&mut self, line: usize) -> io::Result<()> {
+        self.set_line(line)?;
+        self.draw_screen()?;
+
+        Ok(())
+    }
+
+    fn set_line(&mut self, line: usize) -> io::Result<()> {
+        let height = self.last_row()? as usize;
+        let line = min(line, self.buffer.len() - 1);
+
+        if line < self.index {
+            self.index = line;
+        } else if line > self.index + height {
+            self.index = line - height;
+        }
+
+        let row = (line - self.index) as u16;
+        self.cursor_position.set_row(row);
+        self.stored_position.set_row(row);
+
+        let len = self.buffer[line].len();
+        let max_col = min(len.saturating_sub(1), u16::MAX as usize) as u16;
+        self.cursor_position.set_col(min(self.stored_position.col(), max_col));
+
+        Ok(())
+    }
+
+    /// Regenerates the buffer, keeping the cursor on the same entry when it
+    /// still exists.
+    pub fn refresh(&mut self) -> io::Result<()> {
+        self.reload()?;
+        self.draw_screen()?;
+
+        Ok(())
+    }
+
+    /// Same as `refresh`, without drawing anything.
+    fn reload(&mut self) -> io::Result<()> {
+        self.load(false);
+        Ok(())
+    }
+
+    /// Sorts and filters the entries again after the settings changed,
+    /// reading the directory again only when the sort needs metadata, or
+    /// when it is flat.
+    pub fn resort(&mut self) -> io::Result<()> {
+        if self.flat.is_some()
+            || self.settings.sort.needs_metadata() && self.entries.iter().any(|entry| !entry.has_metadata()) {
+            return self.refresh();
+        }
+        self.rearrange()?;
+        self.draw_screen()?;
+
+        Ok(())
+    }
+
+    /// Rebuilds the buffer, keeping the cursor on the same entry when it is
+    /// still listed, or else on the closest directory it was listed under.
+    fn rearrange(&mut self) -> io::Result<()> {
+        let line = self.current_line();
+        let current = self.buffer.get(line).and_then(|line| line.path().cloned());
+
+        self.build_buffer();
+
+        let line = current
+            .and_then(|current| self.line_of(&current))
+            .unwrap_or(line);
+        self.measure();
+
+        self.set_line(line)
+    }
+
+    /// The line listing `path`, or else the closest directory under `cd`
+    /// that it is listed under.
+    fn line_of(&self, path: &Path) -> Option<usize> {
+        let position = |path: &Path| self.buffer.iter().position(|line| line.path().is_some_and(|listed| listed == path));
+        position(path).or_else(|| path.ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&self.cd) && *dir != self.cd)
+            .find_map(position))
+    }
+
+    /// Points du mode at the entries listed in both panes, or stops it when
+    /// it is off. Entries inside archives take no space of their own, so
+    /// they are left out.
+    fn measure(&mut self) {
+        if self.in_other_pane {
+            return;
+        }
+        let mut paths = vec![];
+        if self.settings.du {
+            let views = [(&self.cd, &self.buffer)].into_iter()
+                .chain(self.pane.as_ref().map(|pane| (&pane.view.cd, &pane.view.buffer)));
+            for (cd, buffer) in views {
+                if archive::split(self.vfs.as_ref(), cd).is_some() {
+                    continue;
+                }
+                paths.extend(buffer.iter().filter_map(|line| Some(line.entry()?.path.clone())));
+            }
+        }
+        self.du.measure(&self.vfs, &paths);
+    }
+
+    pub fn current_path(&self) -> Option<&PathBuf> {
+        self.buffer.get(self.current_line())?.path()
+    }
+
+    /// The entry under the cursor, unless it is on a link or the header.
+    pub fn current_entry(&self) -> Option<&Rc<Entry>> {
+        self.buffer.get(self.current_line())?.entry()
+    }
+
+    /// Marks every entry listed, which leaves out the ones the filter hides,
+    /// or unmarks them all when they already are.
+    pub fn toggle_all_selected(&mut self) -> io::Result<()> {
+        let listed: Vec<PathBuf> = self.buffer.iter()
+            .filter_map(|line| Some(line.entry()?.path.clone()))
+            .filter(|path| self.picker.as_ref().is_none_or(|picker| picker.accepts(self.vfs.as_ref(), path)))
+            .collect();
 
-            self.index
-        } else if self.index < height / 2 + 1 {
-            0
+        if listed.iter().all(|path| self.selected.contains(path)) {
+            for path in &listed {
+                self.selected.remove(path);
+            }
         } else {
-            self.index - height / 2 - 1
+            self.selected.extend(listed);
+        }
+        self.draw_screen()?;
+
+        Ok(())
+    }
+
+    /// Marks or unmarks the entry under the cursor, then moves down.
+    pub fn toggle_selected(&mut self) -> io::Result<()> {
+        let path = match self.current_entry() {
+            Some(entry) => entry.path.clone(),
+            None => return self.move_cursor_down()
+        };
+        if self.picker.as_ref().is_some_and(|picker| !picker.accepts(self.vfs.as_ref(), &path)) {
+            self.status = format!("cannot pick {}", path.display());
+            return self.move_cursor_down();
+        }
+        if !self.selected.remove(&path) {
+            self.selected.insert(path);
+        }
+        self.move_cursor_down()
+    }
+
+    /// Picks the marked entries, or the entry under the cursor if none are
+    /// marked, and leaves fap.
+    pub fn choose(&mut self) {
+        let picker = match &self.picker {
+            Some(picker) => picker,
+            None => return
         };
+        let chosen: Vec<PathBuf> = self.targets().into_iter()
+            .filter(|path| picker.accepts(self.vfs.as_ref(), path))
+            .collect();
+        if chosen.is_empty() {
+            self.status = "nothing to pick here, mark entries with v".to_string();
+            return;
+        }
+
+        self.chosen = chosen;
+        self.output = Some(self.cd.clone());
+        self.quit = true;
+    }
+
+    /// The marked entries, or the entry under the cursor if none are marked.
+    pub fn targets(&self) -> Vec<PathBuf> {
+        if !self.selected.is_empty() {
+            return self.selected.iter().cloned().collect();
+        }
+        self.current_entry()
+            .map(|entry| entry.path.clone())
+            .into_iter()
+            .collect()
+    }
+
+    /// Opens a second pane at `cd` to the right of the current one.
+    pub fn open_pane(&mut self, cd: PathBuf) -> io::Result<()> {
+        self.check_dir(&cd)?;
+        let cd = cd.absolutize()?.to_path_buf();
+        self.pane = Some(Pane::new(cd));
+        self.focus_right = false;
+        self.with_other_pane(|app| app.generate_buffer());
 
-        execute!(io::stderr(), SavePosition)?;
         self.draw_screen()?;
-        execute!(io::stderr(), RestorePosition)?;
 
-        return Ok(());
+        Ok(())
     }
 
-    pub fn move_cursor_to_top(&mut self) -> io::Result<()> {
-        let (col, _) = self.cursor_position.get();
+    pub fn close_pane(&mut self) -> io::Result<()> {
+        self.pane = None;
+        self.focus_right = false;
+        self.measure();
+        self.draw_screen()?;
+
+        Ok(())
+    }
+
+    pub fn switch_pane(&mut self) -> io::Result<()> {
+        if let Some(mut pane) = self.pane.take() {
+            pane.swap(self);
+            self.pane = Some(pane);
+            self.focus_right = !self.focus_right;
+            self.draw_screen()?;
+        }
+        Ok(())
+    }
+
+    /// Makes the pane without focus show the same directory as this one.
+    pub fn sync_other_pane(&mut self) -> io::Result<()> {
+        let cd = self.cd.clone();
+        self.with_other_pane(|app| {
+            app.cd = cd;
+            app.generate_buffer();
+            app.index = 0;
+            app.cursor_position = Position::new(START_X, START_Y);
+            app.stored_position = Position::new(START_X, START_Y);
+        });
+        self.draw_screen()?;
+
+        Ok(())
+    }
+
+    pub fn other_pane_dir(&self) -> Option<&PathBuf> {
+        self.pane.as_ref().map(|pane| &pane.view.cd)
+    }
+
+    /// Runs `fun` with the pane without focus swapped into the `App`.
+    /// Returns `None` when not in commander mode.
+    pub fn with_other_pane<R>(&mut self, fun: impl FnOnce(&mut App) -> R) -> Option<R> {
+        let mut pane = self.pane.take()?;
+        pane.swap(self);
+        self.in_other_pane = true;
+        let output = fun(self);
+        self.in_other_pane = false;
+        pane.swap(self);
+        self.pane = Some(pane);
+        // Measured once both views are back at hand.
+        self.measure();
+        Some(output)
+    }
+
+    /// Copies, or moves, the targeted entries into `dest` and refreshes
+    /// both panes.
+    pub fn transfer(&mut self, dest: &Path, keep_source: bool) -> io::Result<()> {
+        let targets = self.targets();
+        let mut count = 0;
+        let mut result = Ok(());
+        for path in &targets {
+            let transferred = if keep_source {
+                file_ops::copy_into(self.vfs.as_ref(), path, dest)
+            } else {
+                file_ops::move_into(self.vfs.as_ref(), path, dest)
+            };
+            match transferred {
+                Ok(_) => {
+                    self.selected.remove(path);
+                    count += 1;
+                },
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+
+        self.with_other_pane(|app| app.reload());
+        self.reload()?;
+        self.draw_screen()?;
+
+        let verb = if keep_source { "copied" } else { "moved" };
+        self.status = format!("{} {} of {} to {}", verb, count, targets.len(), dest.display());
+        result
+    }
+
+    /// Extracts the targeted archives into `dest`, or `cd` when there is
+    /// none, and refreshes both panes.
+    pub fn extract(&mut self, dest: Option<&str>) -> io::Result<()> {
+        let dest = match dest {
+            Some(dest) => commands::resolve_path(&self.cd, dest),
+            None => self.cd.clone()
+        };
+        if archive::split(self.vfs.as_ref(), &dest).is_some() {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "cannot write inside an archive"));
+        }
+        let archives = self.targets();
+        if archives.is_empty() {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "no archive to extract"));
+        }
+        if let Some(path) = archives.iter().find(|path| !archive::is_archive(self.vfs.as_ref(), path)) {
+            let msg = format!("{} is not an archive", path.display());
+            return Err(io::Error::new(ErrorKind::InvalidInput, msg));
+        }
+
+        let mut result = Ok(());
+        for path in archives {
+            let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let (job_path, job_dest, job_name) = (path.clone(), dest.clone(), name.clone());
+            let extracted = self.run_job(move |report| archive::extract(&job_path, &job_dest, &|done, total| {
+                report(format!("extracting {}: {}/{} files", job_name, done, total));
+            }))?;
+            match extracted {
+                Ok((root, skipped)) => {
+                    self.selected.remove(&path);
+                    self.status = format!("extracted {} to {}", name, root.display());
+                    if skipped > 0 {
+                        self.status += &format!(", left out {} hard links to missing files", skipped);
+                    }
+                },
+                Err(e) => {
+                    result = Err(io::Error::new(e.kind(), format!("{}: {}", name, e)));
+                    break;
+                }
+            }
+        }
+
+        self.with_other_pane(|app| app.reload());
+        self.reload()?;
+        self.draw_screen()?;
+
+        result
+    }
+
+    /// Packs the targeted entries into the archive at `name`, whose suffix
+    /// picks the format, and refreshes both panes.
+    pub fn compress(&mut self, name: &str) -> io::Result<()> {
+        if archive::split(self.vfs.as_ref(), &self.cd).is_some() {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "cannot write inside an archive"));
+        }
+        let sources = self.targets();
+        if sources.is_empty() {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "nothing to archive"));
+        }
+
+        let dest = commands::resolve_path(&self.cd, name);
+        let job_sources = sources.clone();
+        let job_name = name.to_string();
+        let created = self.run_job(move |report| archive::create(&dest, &job_sources, &|done, total| {
+            report(format!("archiving into {}: {}/{} files", job_name, done, total));
+        }))?;
+
+        if created.is_ok() {
+            for path in &sources {
+                self.selected.remove(path);
+            }
+        }
+        self.with_other_pane(|app| app.reload());
+        self.reload()?;
+        self.draw_screen()?;
+
+        let count = created.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", name, e)))?;
+        self.status = format!("archived {} files into {}", count, name);
+        Ok(())
+    }
+
+    /// Runs `job` on a thread of its own, showing the progress it reports
+    /// in the status line until it is done. Keys pressed meanwhile are
+    /// dropped rather than acted upon afterwards.
+    fn run_job<T: Send + 'static>(
+        &mut self,
+        job: impl FnOnce(&dyn Fn(String)) -> io::Result<T> + Send + 'static
+    ) -> io::Result<io::Result<T>> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let handle = std::thread::spawn(move || job(&|message| {
+            let _ = sender.send(message);
+        }));
+
+        while !handle.is_finished() {
+            if let Some(message) = receiver.try_iter().last() {
+                self.status = message;
+                self.draw_screen()?;
+            }
+            if poll(LOADING_POLL)? {
+                read()?;
+            }
+        }
+
+        Ok(handle.join().unwrap_or_else(|_| Err(io::Error::other("the job panicked"))))
+    }
+
+    /// Asks for a path after `prompt`, then hands it to `run` as typed,
+    /// spaces and quotes included.
+    pub fn prompt_path(&mut self, prompt: &str, run: fn(&mut Self, &str) -> io::Result<()>) -> io::Result<()> {
+        let line = CommandLine::default().read_line(prompt, &|before| commands::complete_line_path(self, before));
+        self.renderer.invalidate();
+
+        if let Some(line) = line? {
+            if let Err(e) = run(self, &line) {
+                self.status = e.to_string();
+            }
+        }
+        self.draw_screen()?;
+
+        Ok(())
+    }
+
+    pub fn change_dir(&mut self, path: PathBuf) -> io::Result<()> {
+        if archive::split(self.vfs.as_ref(), &path).is_none() {
+            self.check_dir(&path)
+                .map_err(|e| io::Error::new(e.kind(), format!("cannot read {}: {}", path.display(), e)))?;
+        }
+        self.cd = path.absolutize()?.to_path_buf();
+        self.generate_buffer();
+        self.index = 0;
+        self.cursor_position = Position::new(START_X, START_Y);
+        self.draw_screen()?;
+        self.stored_position = Position::new(START_X, START_Y);
+
+        Ok(())
+    }
+
+    pub fn loop_fn(
+        &mut self, 
+        fun: fn(&mut Self) -> io::Result<()>
+    ) -> io::Result<()> {
+        let times = self.command_state.number.unwrap_or(1);
+        for _ in 0..times {
+            fun(self)?;
+        }
+        self.command_state.number = None;
+
+        Ok(())
+    }
+
+    pub fn go_to_parent_dir(&mut self) -> io::Result<()> {
+        if let Some(root) = self.cd.parent() {
+            self.change_dir(root.to_path_buf())?;
+        }
+        Ok(())
+    }
     
-        let (_, len, _) = self.buffer[self.index as usize];
-        let max_col = max(len - 1, 0) as u16;
-        
-        execute!(io::stderr(), MoveToRow(0))?;
+    pub fn move_cursor_to_first_line(&mut self) -> io::Result<()> {
+        self.index = 0;
         self.stored_position.set_row(0);
         self.cursor_position.set_row(0);
+        self.fit_col(self.buffer[0].len());
+
+        Ok(())
+    }
     
-        if max_col < col {
-            execute!(io::stderr(), MoveToColumn(LEFT_SIDE_PADDING + max_col))?;
-            self.cursor_position.set_col(max_col);
-        } else if col < min(self.stored_position.col(), max_col) {
-            let new_col = min(self.stored_position.col(), max_col);
-            execute!(io::stderr(), MoveToColumn(LEFT_SIDE_PADDING + new_col))?;
-            self.cursor_position.set_col(new_col);
+    pub fn move_cursor_to_last_line(&mut self) -> io::Result<()> {
+        let height = self.last_row()? as usize;
+        let last = self.buffer.len() - 1;
+    
+        if last <= height {
+            self.index = 0;
+        } else {
+            self.index = last - height;
         }
-        execute!(io::stderr(), SavePosition)?;
-        self.draw_screen()?;
-        execute!(io::stderr(), RestorePosition)?;
-        
-        return Ok(());
+        self.stored_position.set_row((last - self.index) as u16);
+        self.cursor_position.set_row(self.stored_position.row());
+        self.fit_col(self.buffer[last].len());
+
+        Ok(())
+    }
+
+    pub fn move_screen_down_line(&mut self) -> io::Result<()> {
+        let height = self.last_row()? as usize;
+
+        if self.buffer.len() <= height || self.index + 1 >= self.buffer.len() {
+            return Ok(());
+        }
+
+        self.index += 1;
+
+        if self.current_line() >= self.buffer.len() && self.cursor_position.row() > 0 {
+            self.cursor_position.move_up();
+            self.stored_position.move_up();
+        }
+
+        Ok(())
     }
     
-    pub fn move_cursor_to_middle(&mut self) -> io::Result<()> {
-        let (col, _) = self.cursor_position.get();
-        let midpoint = (terminal::window_size()?.rows - BOTTOM_RESERVED - 1) / 2;
-        let i = min((self.index + midpoint) as usize, self.buffer.len() - 1) as u16;
-        let row = i - self.index;
-    
-        let (_, len, _) = self.buffer[i as usize];
-        let max_col = max(len - 1, 0) as u16;
+    pub fn move_screen_up_line(&mut self) -> io::Result<()> {
+        let height = self.last_row()? as usize;
+
+        if self.buffer.len() <= height || self.index == 0 {
+            return Ok(());
+        }
+
+        self.index -= 1;
+
+        Ok(())
+    }
+
+    pub fn move_screen_down_page(&mut self) -> io::Result<()> {
+        let height = self.last_row()? as usize;
+        const PAGE_GAP: usize = 2;
+        
+        self.index = min(
+            self.index.saturating_add(height.saturating_sub(PAGE_GAP)),
+            self.buffer.len() - 1
+        );
+        
+        self.cursor_position.set_row(0);
+        self.stored_position.set_row(0);
+
+        Ok(())
+    }
+
+    pub fn move_screen_up_page(&mut self) -> io::Result<()> {
+        let height = self.last_row()? as usize;
+        const PAGE_GAP: usize = 2;
+
+        self.index = self.index.saturating_sub(height.saturating_sub(PAGE_GAP));
+
+        let row = min(self.buffer.len() - self.index - 1, height) as u16;
         
-        execute!(io::stderr(), MoveToRow(row))?;
-        self.stored_position.set_row(row);
         self.cursor_position.set_row(row);
-    
-        if max_col < col {
-            execute!(io::stderr(), MoveToColumn(LEFT_SIDE_PADDING + max_col))?;
-            self.cursor_position.set_col(max_col);
-        } else if col < min(self.stored_position.col(), max_col) {
-            let new_col = min(self.stored_position.col(), max_col);
-            execute!(io::stderr(), MoveToColumn(LEFT_SIDE_PADDING + new_col))?;
-            self.cursor_position.set_col(new_col);
+        self.stored_position.set_row(row);
+
+        Ok(())
+    }
+
+    pub fn move_down_half_page(&mut self) -> io::Result<()> {
+        let row = self.cursor_position.row() as usize;
+        let height = self.last_row()? as usize;
+        let last = self.buffer.len() - 1;
+        let max_index = last.saturating_sub(height);
+        
+        if last <= height || self.index >= max_index {
+            let row = *[
+                last - self.index,
+                height,
+                row + height / 2 + 1, 
+            ].iter().min().unwrap_or(&row) as u16;
+
+            self.cursor_position.set_row(row);
+            self.stored_position.set_row(row);
+        } else {
+            self.index = min(self.index + height / 2 + 1, max_index);
         }
-        execute!(io::stderr(), SavePosition)?;
-        self.draw_screen()?;
-        execute!(io::stderr(), RestorePosition)?;
+
+        Ok(())
+    }
+
+    pub fn move_up_half_page(&mut self) -> io::Result<()> {
+        let row = self.cursor_position.row();
+        let height = self.last_row()?;
+        
+        if self.index == 0 {
+            let row = row - min(row, height / 2 + 1);
+
+            self.cursor_position.set_row(row);
+            self.stored_position.set_row(row);
+        } else {
+            self.index = self.index.saturating_sub(height as usize / 2 + 1);
+        }
+
+        Ok(())
+    }
+
+    pub fn move_cursor_to_top(&mut self) -> io::Result<()> {
+        self.stored_position.set_row(0);
+        self.cursor_position.set_row(0);
+        self.fit_col(self.buffer[self.index].len());
         
-        return Ok(());
+        Ok(())
     }
     
-    pub fn move_cursor_to_bottom(&mut self) -> io::Result<()> {
-        let (col, _) = self.cursor_position.get();
-        let bottom = terminal::window_size()?.rows - BOTTOM_RESERVED - 1;
-        let i = min((self.index + bottom) as usize, self.buffer.len() - 1) as u16;
-        let row = i - self.index;
+    pub fn move_cursor_to_middle(&mut self) -> io::Result<()> {
+        let midpoint = self.last_row()? / 2;
+        self.move_cursor_to_row(midpoint)
+    }
     
-        let (_, len, _) = self.buffer[i as usize];
-        let max_col = max(len - 1, 0) as u16;
+    pub fn move_cursor_to_bottom(&mut self) -> io::Result<()> {
+        let bottom = self.last_row()?;
+        self.move_cursor_to_row(bottom)
+    }
+
+    /// Moves the cursor to `row` on screen, or to the last line if the
+    /// buffer ends above it.
+    fn move_cursor_to_row(&mut self, row: u16) -> io::Result<()> {
+        let line = min(self.index + row as usize, self.buffer.len() - 1);
+        let row = (line - self.index) as u16;
         
-        execute!(io::stderr(), MoveToRow(row))?;
         self.stored_position.set_row(row);
         self.cursor_position.set_row(row);
-    
-        if max_col < col {
-            execute!(io::stderr(), MoveToColumn(LEFT_SIDE_PADDING + max_col))?;
-            self.cursor_position.set_col(max_col);
-        } else if col < min(self.stored_position.col(), max_col) {
-            let new_col = min(self.stored_position.col(), max_col);
-            execute!(io::stderr(), MoveToColumn(LEFT_SIDE_PADDING + new_col))?;
-            self.cursor_position.set_col(new_col);
-        }
-        execute!(io::stderr(), SavePosition)?;
-        self.draw_screen()?;
-        execute!(io::stderr(), RestorePosition)?;
+        self.fit_col(self.buffer[line].len());
         
-        return Ok(());
+        Ok(())
     }
     
     pub fn move_cursor_left(&mut self) -> io::Result<()> {
         let (col, _) = self.cursor_position.get();
-        if col <= 0 {
+        if col == 0 {
             return Ok(());
         }
-        execute!(io::stderr(), MoveLeft(1), SavePosition)?;
         self.stored_position.set_col(col - 1);
         self.cursor_position.move_left();
-        self.write_bottom()?;
-        execute!(io::stderr(), RestorePosition)?;
-        return Ok(());
+        Ok(())
     }
     
     pub fn move_cursor_down(&mut self) -> io::Result<()> {
-        let rows = terminal::window_size()?.rows;
-    
-        let (col, row) = self.cursor_position.get();
-    
-        if (self.index + self.stored_position.row()) as usize >= self.buffer.len() - 1 {
+        let line = self.current_line();
+        if line + 1 >= self.buffer.len() {
             return Ok(());
         }
     
-        let (_, len, _) = self.buffer[(self.index + row + 1) as usize];
-        let max_col = max(len - 1, 0) as u16;
-    
-        if self.stored_position.row() >= rows - BOTTOM_RESERVED - 1 {
+        if self.stored_position.row() >= self.last_row()? {
             self.index += 1;
         } else {
-            execute!(io::stderr(), MoveDown(1))?;
             self.stored_position.move_down();
             self.cursor_position.move_down();
         }
-    
-        if max_col < col {
-            execute!(io::stderr(), MoveToColumn(LEFT_SIDE_PADDING + max_col))?;
-            self.cursor_position.set_col(max_col);
-        } else if col < min(self.stored_position.col(), max_col) {
-            let new_col = min(self.stored_position.col(), max_col);
-            execute!(io::stderr(), MoveToColumn(LEFT_SIDE_PADDING + new_col))?;
-            self.cursor_position.set_col(new_col);
-        }
-    
-        execute!(io::stderr(), SavePosition)?;
-        self.draw_screen()?;
-        execute!(io::stderr(), RestorePosition)?;
-    
-        return Ok(());
+        self.fit_col(self.buffer[line + 1].len());
+
+        Ok(())
     }
     
     pub fn move_cursor_up(&mut self) -> io::Result<()> {
-        let (col, row) = self.cursor_position.get();
-    
-        if self.index + row == 0 {
+        let line = self.current_line();
+        if line == 0 {
             return Ok(());
         }
-    
-        let (_, len, _) = self.buffer[(self.index + row - 1) as usize];
-        let max_col = max(len - 1, 0) as u16;
         
         if self.stored_position.row() == 0 && self.index > 0 {
             self.index -= 1;
         } else {
-            execute!(io::stderr(), MoveUp(1))?;
             self.stored_position.move_up();
             self.cursor_position.move_up();
         }
-    
-        if max_col < col {
-            execute!(io::stderr(), MoveToColumn(LEFT_SIDE_PADDING + max_col))?;
-            self.cursor_position.set_col(max_col);
-        } else if col < min(self.stored_position.col(), max_col) {
-            let new_col = min(self.stored_position.col(), max_col);
-            execute!(io::stderr(), MoveToColumn(LEFT_SIDE_PADDING + new_col))?;
-            self.cursor_position.set_col(new_col);
-        }
-        execute!(io::stderr(), SavePosition)?;
-        self.draw_screen()?;
-        execute!(io::stderr(), RestorePosition)?;
+        self.fit_col(self.buffer[line - 1].len());
         
-        return Ok(());
+        Ok(())
     }
     
     pub fn move_cursor_right(&mut self) -> io::Result<()> {
-        let (col, row) = self.cursor_position.get();
-        let (_, len, _) = self.buffer[row as usize];
-        let max_index = len;
-        if col + 1 >= max_index as u16 {
+        let col = self.cursor_position.col();
+        let len = self.buffer[self.current_line()].len();
+        if col as usize + 1 >= len || col == u16::MAX {
             return Ok(());
         }
-        execute!(io::stderr(), MoveRight(1), SavePosition)?;
         self.stored_position.set_col(col + 1);
         self.cursor_position.move_right();
-        self.write_bottom()?;
-        execute!(io::stderr(), RestorePosition)?; 
-        return Ok(());
+        Ok(())
+    }
+
+    /// Line of the buffer under the cursor.
+    pub fn current_line(&self) -> usize {
+        self.index + self.cursor_position.row() as usize
+    }
+
+    /// Last screen row the buffer is drawn on.
+    fn last_row(&self) -> io::Result<u16> {
+        Ok(terminal::window_size()?.rows.saturating_sub(BOTTOM_RESERVED + 1))
     }
+
+    /// Keeps the cursor within a line `len` columns wide, going back to the
+    /// column it was last moved to when the line is wide enough.
+    fn fit_col(&mut self, len: usize) {
+        let max_col = min(len.saturating_sub(1), u16::MAX as usize) as u16;
+        let col = self.cursor_position.col();
     
-    pub fn select_entry(&mut self) -> io::Result<()> {
-        let (path, _, _) = &self.buffer[(self.index + self.stored_position.row()) as usize];
-        
-        if path.is_none() {
-            return Ok(());
+        if max_col < col {
+            self.cursor_position.set_col(max_col);
+        } else if col < min(self.stored_position.col(), max_col) {
+            self.cursor_position.set_col(min(self.stored_position.col(), max_col));
         }
-        
-        let path = path.as_ref().unwrap().as_path();
+    }
     
-        if path.is_dir() {
-            self.cd = path.to_path_buf();
-            self.generate_buffer();
-            execute!(io::stderr(), 
-                Clear(ClearType::All), 
-                MoveTo(LEFT_SIDE_PADDING,0)
-            )?;
-            self.index = 0;
-            self.cursor_position = Position::new(START_X, START_Y);
-            self.draw_screen()?;
-            execute!(io::stderr(), MoveTo(START_X, START_Y))?;
-            self.stored_position = Position::new(START_X, START_Y);
-        } else {
-            if path.ends_with(".desktop") {
+    pub fn select_entry(&mut self) -> io::Result<()> {
+        let (path, is_dir) = match &self.buffer[self.current_line()] {
+            Line::Entry { entry, .. } => (entry.path.clone(), entry.is_dir()),
+            line => match line.path() {
+                Some(path) => (path.clone(), true),
+                None => return Ok(())
+            }
+        };
+        let path = path.as_path();
     
-                return Ok(());
+        if is_dir || archive::is_archive(self.vfs.as_ref(), path) {
+            self.change_dir(path.to_path_buf())?;
+        } else if self.picker.is_some() {
+            self.choose();
+        } else if archive::split(self.vfs.as_ref(), path).is_some() {
+            // Only opened, never run, since it was not on disk until now.
+            let copy = match self.current_file() {
+                Some(copy) => copy,
+                None => return Ok(())
+            };
+            match self.openers.matching(&copy).first() {
+                Some(&i) => self.open_with(i, &copy),
+                None => if let Err(e) = opener::open(&copy) {
+                    self.status = e.to_string();
+                }
+            }
+        } else if let Some(&i) = self.openers.matching(path).first() {
+            self.open_with(i, path);
+        } else {
+            if path.extension().is_some_and(|extension| extension == "desktop") {
+                return self.launch_desktop_entry(path);
             }
     
-            if path.is_executable() {
-                let path = path.absolutize().unwrap().display().to_string();
-                std::process::Command::new(&path)
-                    .stdin(Stdio::piped())
-                    .stderr(Stdio::piped())
-                    .stdout(Stdio::piped())
-                    .spawn()
-                    .expect(&format!("could not spawn process {}", &path));
-            } else {
-                opener::open(path).map_err(|_e| {
-                    return io::Error::new(
-                        ErrorKind::Other,
-                        _e.to_string()
-                    )
-                })?;
+            if self.is_executable(path) {
+                if self.settings.exec == ExecPolicy::Never {
+                    self.open_path_in(path, "PAGER", "less")?;
+                } else if self.may_run(path)? {
+                    self.run_program(path, "", false)?;
+                }
+            } else if let Err(e) = opener::open(path) {
+                self.status = e.to_string();
             }
         }
     
-        return Ok(());
+        Ok(())
     }
     
+    /// Fails unless `path` is a directory that can be listed.
+    fn check_dir(&self, path: &Path) -> io::Result<()> {
+        self.vfs.list(path).map(drop)
+    }
+
+    /// Whether `path` is a file that can be run.
+    fn is_executable(&self, path: &Path) -> bool {
+        self.vfs.stat(path).is_ok_and(|stat| stat.executable)
+    }
+
+    /// Directory programs are started in: `cd`, or the directory holding
+    /// the archive when `cd` is inside one.
+    fn work_dir(&self) -> PathBuf {
+        match archive::split(self.vfs.as_ref(), &self.cd) {
+            Some((archive, _)) => archive.parent().unwrap_or(Path::new("/")).to_path_buf(),
+            None => self.cd.clone()
+        }
+    }
+
+    /// The file under the cursor. Files inside archives are extracted to a
+    /// temporary directory first, so that other programs can open them.
+    /// Sets the status when there is no such file.
+    fn current_file(&mut self) -> Option<PathBuf> {
+        let path = match &self.buffer[self.current_line()] {
+            Line::Entry { entry, .. } if !entry.is_dir() => entry.path.clone(),
+            _ => {
+                self.status = "no file to open here".to_string();
+                return None;
+            }
+        };
+
+        match archive::local_copy(self.vfs.as_ref(), &path) {
+            Ok(path) => Some(path),
+            Err(e) => {
+                self.status = format!("cannot extract {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Lets the user pick which of the matching opener rules, or the system
+    /// default, opens the file under the cursor.
+    pub fn open_with_menu(&mut self) -> io::Result<()> {
+        let path = match self.current_file() {
+            Some(path) => path,
+            None => return Ok(())
+        };
+
+        let rules = self.openers.matching(&path);
+        let mut items: Vec<String> = rules.iter().map(|&i| self.openers.rules[i].title()).collect();
+        items.push("system default &".to_string());
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        match self.menu(&format!("open {} with", name), &items)? {
+            Some(i) if i < rules.len() => self.open_with(rules[i], &path),
+            Some(_) => {
+                if let Err(e) = opener::open(&path) {
+                    self.status = e.to_string();
+                }
+            },
+            None => ()
+        }
+
+        Ok(())
+    }
+
+    /// Opens the file under the cursor in the program named by the
+    /// environment variable `var`, or `fallback` when it is unset.
+    pub fn open_in(&mut self, var: &str, fallback: &str) -> io::Result<()> {
+        let path = match self.current_file() {
+            Some(path) => path,
+            None => return Ok(())
+        };
+
+        self.open_path_in(&path, var, fallback)
+    }
+
+    fn open_path_in(&mut self, path: &Path, var: &str, fallback: &str) -> io::Result<()> {
+        let program = std::env::var(var)
+            .ok()
+            .filter(|program| !program.trim().is_empty())
+            .unwrap_or_else(|| fallback.to_string());
+        // Through the shell, since $EDITOR often carries arguments, e.g. "code -w".
+        let mut command = std::process::Command::new("sh");
+        command.arg("-c")
+            .arg(format!("{} {}", program, posix_quote(&path.to_string_lossy())))
+            .current_dir(self.work_dir());
+
+        self.status = match self.run_foreground(&mut command) {
+            Ok(status) if status.success() => String::new(),
+            Ok(status) => format!("{} exited with {}", program, status),
+            Err(e) => format!("{}: {}", program, e)
+        };
+
+        Ok(())
+    }
+
+    /// Asks for arguments, then runs the executable under the cursor with
+    /// them, either in the foreground or with its output shown in a pager.
+    pub fn run_with_args(&mut self, foreground: bool) -> io::Result<()> {
+        let path = match self.current_path() {
+            Some(path) if self.is_executable(path) => path.clone(),
+            _ => {
+                self.status = "no executable here".to_string();
+                return Ok(());
+            }
+        };
+
+        if !self.may_run(&path)? {
+            return Ok(());
+        }
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let mut args_line = std::mem::take(&mut self.args_line);
+        let args = args_line.read_line(&format!("{} ", name), &|before| commands::complete_args(self, before));
+        self.args_line = args_line;
+        self.renderer.invalidate();
+
+        if let Some(args) = args? {
+            self.run_program(&path, &args, foreground)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks the `exec` policy before running the executable at `path`,
+    /// asking for confirmation unless it is trusted.
+    pub fn may_run(&mut self, path: &Path) -> io::Result<bool> {
+        self.may_launch(path, exec::interpreter(path))
+    }
+
+    /// Same as `may_run`, for a file that `runs` something, e.g. a script
+    /// or a desktop entry.
+    pub fn may_launch(&mut self, path: &Path, runs: String) -> io::Result<bool> {
+        match self.settings.exec {
+            ExecPolicy::Never => {
+                self.status = "not running executables, exec=never".to_string();
+                return Ok(false);
+            },
+            ExecPolicy::Trusted if exec::is_trusted(path, &self.settings.trusted) => return Ok(true),
+            _ => ()
+        }
+
+        let path = path.absolutize()?.to_path_buf();
+        self.confirm(&[
+            "Run this program? (y/n)".to_string(),
+            format!("  {}", path.display()),
+            format!("  {}", runs),
+        ])
+    }
+
+    /// Shows `lines` at the bottom of the screen until y or n is pressed.
+    /// Anything else counts as n.
+    pub fn confirm(&mut self, lines: &[String]) -> io::Result<bool> {
+        let (mut frame, _) = self.frame()?;
+        menu::draw_dialog(&mut frame, lines);
+        let cursor = (0, frame.height().saturating_sub(1));
+        self.renderer.render(frame, cursor)?;
+
+        loop {
+            match read()? {
+                Event::Key(KeyEvent{code: KeyCode::Char('y' | 'Y'), ..}) => return Ok(true),
+                Event::Key(_) => return Ok(false),
+                _ => ()
+            }
+        }
+    }
+
+    /// Runs the executable at `path` from `cd`, with `args` passed through
+    /// the shell as typed. In the foreground the program gets the terminal,
+    /// and fap waits for Enter before coming back so that its output can be
+    /// read; otherwise the output is shown in a pager.
+    pub fn run_program(&mut self, path: &Path, args: &str, foreground: bool) -> io::Result<()> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let title = format!("{} {}", name, args).trim_end().to_string();
+        let program = format!("{} {}", posix_quote(&path.to_string_lossy()), args);
+
+        let mut command = std::process::Command::new("sh");
+        command.current_dir(self.work_dir());
+
+        if foreground {
+            command.arg("-c").arg(format!(
+                "{}\nstatus=$?; printf '\\n[%s exited with %s, press Enter to return to fap] ' {} $status; read _; exit $status",
+                program, posix_quote(&name)
+            ));
+            self.status = match self.run_foreground(&mut command) {
+                Ok(status) => format!("{}: {}", title, status),
+                Err(e) => format!("{}: {}", title, e)
+            };
+            return Ok(());
+        }
+
+        command.arg("-c").arg(program);
+        match Pager::spawn(title.clone(), &mut command) {
+            Ok(pager) => self.show_pager(pager)?,
+            Err(e) => self.status = format!("{}: {}", title, e)
+        }
+
+        Ok(())
+    }
+
+    /// Shows `pager` until it is closed, then reloads the listing in case
+    /// the program changed the directory. Closing it early kills the program.
+    pub fn show_pager(&mut self, mut pager: Pager) -> io::Result<()> {
+        loop {
+            let mut frame = Frame::for_terminal()?;
+            pager.fit(frame.height());
+            pager.draw(&mut frame);
+            let cursor = (0, frame.height().saturating_sub(1));
+            self.renderer.render(frame, cursor)?;
+
+            let timeout = if pager.is_running() { LOADING_POLL } else { IDLE_POLL };
+            if !poll(timeout)? {
+                pager.poll();
+                continue;
+            }
+
+            let half = (pager.rows / 2).max(1) as isize;
+            let page = pager.rows.max(1) as isize;
+            match read()? {
+                Event::Key(KeyEvent{code, modifiers, ..}) if modifiers.contains(KeyModifiers::CONTROL) => {
+                    match code {
+                        KeyCode::Char('d') => pager.scroll(half),
+                        KeyCode::Char('u') => pager.scroll(-half),
+                        KeyCode::Char('f') => pager.scroll(page),
+                        KeyCode::Char('b') => pager.scroll(-page),
+                        KeyCode::Char('c') => break,
+                        _ => ()
+                    }
+                },
+                Event::Key(KeyEvent{code, ..}) => match code {
+                    KeyCode::Char('j') | KeyCode::Down | KeyCode::Enter => pager.scroll(1),
+                    KeyCode::Char('k') | KeyCode::Up => pager.scroll(-1),
+                    KeyCode::Char(' ') | KeyCode::PageDown => pager.scroll(page),
+                    KeyCode::PageUp => pager.scroll(-page),
+                    KeyCode::Char('g') | KeyCode::Home => pager.scroll_to_top(),
+                    KeyCode::Char('G') | KeyCode::End => pager.scroll_to_end(),
+                    KeyCode::Char('q') | KeyCode::Esc => break,
+                    _ => ()
+                },
+                _ => ()
+            }
+        }
+
+        pager.poll();
+        self.status = match pager.status {
+            Some(status) => format!("{}: {}", pager.title, status),
+            None => format!("{}: stopped", pager.title)
+        };
+        drop(pager);
+        self.renderer.invalidate();
+        self.reload()?;
+
+        Ok(())
+    }
+
+    /// Opens `path` with the opener rule at `i`. Failures are reported in
+    /// the status line.
+    pub fn open_with(&mut self, i: usize, path: &Path) {
+        let rule = &self.openers.rules[i];
+        let name = rule.command.clone();
+        let mut command = std::process::Command::new("sh");
+        command.arg("-c").arg(rule.command_line(path)).current_dir(self.work_dir());
+
+        if rule.foreground {
+            self.status = match self.run_foreground(&mut command) {
+                Ok(status) if status.success() => String::new(),
+                Ok(status) => format!("{} exited with {}", name, status),
+                Err(e) => format!("{}: {}", name, e)
+            };
+            return;
+        }
+
+        self.spawn_detached(&mut command, &name);
+    }
+
+    /// Starts `command` without a terminal, reaping it on a thread of its
+    /// own. Failures are reported in the status line.
+    pub fn spawn_detached(&mut self, command: &mut std::process::Command, name: &str) {
+        command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+        match command.spawn() {
+            Ok(mut child) => {
+                std::thread::spawn(move || child.wait());
+            },
+            Err(e) => self.status = format!("{}: {}", name, e)
+        }
+    }
+
+    /// Launches the application of the desktop entry at `path`, or opens
+    /// the target of a link entry.
+    pub fn launch_desktop_entry(&mut self, path: &Path) -> io::Result<()> {
+        let entry = match DesktopEntry::load(path) {
+            Ok(entry) => entry,
+            Err(e) => {
+                self.status = format!("{}: {}", path.display(), e);
+                return Ok(());
+            }
+        };
+        if self.settings.exec == ExecPolicy::Never {
+            return self.open_path_in(path, "PAGER", "less");
+        }
+
+        let name = entry.name.clone().unwrap_or_else(|| path.display().to_string());
+        if entry.kind == "Link" {
+            if let Some(url) = &entry.url {
+                if self.may_launch(path, format!("opens {}", url))? {
+                    if let Err(e) = opener::open(url) {
+                        self.status = format!("{}: {}", name, e);
+                    }
+                }
+                return Ok(());
+            }
+        }
+        if entry.kind != "Application" {
+            self.status = format!("{}: cannot launch a desktop entry of type {}", name, entry.kind);
+            return Ok(());
+        }
+        if !entry.is_installed() {
+            self.status = format!("{}: {} is not installed", name, entry.try_exec.unwrap_or_default());
+            return Ok(());
+        }
+
+        let command_line = match entry.command(path, &[]) {
+            Ok(command_line) => command_line,
+            Err(e) => {
+                self.status = format!("{}: {}", name, e);
+                return Ok(());
+            }
+        };
+        if !self.may_launch(path, format!("runs {}", command_line.join(" ")))? {
+            return Ok(());
+        }
+
+        let mut command = std::process::Command::new(&command_line[0]);
+        command.args(&command_line[1..]).current_dir(entry.path.clone().unwrap_or_else(|| self.work_dir()));
+        if entry.terminal {
+            self.status = match self.run_foreground(&mut command) {
+                Ok(status) if status.success() => String::new(),
+                Ok(status) => format!("{} exited with {}", name, status),
+                Err(e) => format!("{}: {}", name, e)
+            };
+        } else {
+            self.spawn_detached(&mut command, &name);
+        }
+
+        Ok(())
+    }
+
+    /// Hands the terminal over to `command` until it exits, then restores
+    /// the screen and reloads the listing.
+    pub fn run_foreground(&mut self, command: &mut std::process::Command) -> io::Result<ExitStatus> {
+        execute!(io::stderr(), LeaveAlternateScreen, EnableLineWrap)?;
+        disable_raw_mode()?;
+
+        let status = command.status();
+
+        enable_raw_mode()?;
+        execute!(io::stderr(), EnterAlternateScreen, DisableLineWrap)?;
+        self.renderer.invalidate();
+        self.reload()?;
+
+        status
+    }
+
     pub fn window_resize(&mut self, width: u16, height: u16) -> io::Result<()> {
         let resized_width = max(width, 5);
         let resized_height = max(height, 5);
@@ -763,30 +2124,13 @@ impl App {
             execute!(io::stderr(), SetSize(resized_width, resized_height))?;
         }
     
-        let (col, row) = self.cursor_position.get();
+        let row = self.cursor_position.row();
         
         self.stored_position.set_row(min(row, resized_height - BOTTOM_RESERVED - 1));
+        self.cursor_position.set_row(self.stored_position.row());
+        self.fit_col(self.buffer[self.current_line()].len());
     
-        let (_, len, _) = self.buffer[(self.index + self.stored_position.row()) as usize];
-        let max_col = max(len - 1, 0) as u16;
-    
-        if max_col < col {
-            execute!(io::stderr(), MoveTo(
-                LEFT_SIDE_PADDING + max_col, 
-                self.stored_position.row()
-            ))?;
-        } else if col < min(self.stored_position.col(), max_col) {
-            execute!(io::stderr(), MoveTo(
-                LEFT_SIDE_PADDING + min(self.stored_position.col(), max_col), 
-                self.stored_position.row()
-            ))?;
-        }
-    
-        execute!(io::stderr(), SavePosition)?;
-        self.draw_screen()?;
-        execute!(io::stderr(), RestorePosition)?;
-    
-        return Ok(());
+        Ok(())
     }
 }
 
@@ -794,21 +2138,116 @@ impl Default for App {
     fn default() -> Self {
         Self { 
             buffer: vec![], 
+            entries: vec![],
+            loader: None,
+            tree: Tree::default(),
+            flat: None,
             cd: std::env::current_dir().unwrap(), 
-            output: String::new(), 
+            output: None, 
             index: 0, 
             stored_position: Position::new(START_X, START_Y), 
             cursor_position: Position::new(START_X, START_Y), 
-            command_state: CommandState::default() 
+            command_state: CommandState::default(),
+            settings: Settings::default(),
+            filters: HashMap::new(),
+            command_line: CommandLine::default(),
+            args_line: CommandLine::default(),
+            shell_line: CommandLine::default(),
+            filter_line: CommandLine::default(),
+            mappings: HashMap::new(),
+            pending_keys: VecDeque::new(),
+            status: String::new(),
+            quit: false,
+            tabs: vec![Tab::new(PathBuf::new())],
+            active_tab: 0,
+            selected: BTreeSet::new(),
+            pane: None,
+            in_other_pane: false,
+            focus_right: false,
+            watcher: Watcher::new().ok(),
+            renderer: Renderer::new(),
+            theme: ColorConfig::default(),
+            openers: Openers::default(),
+            vfs: Arc::new(Archives::new(Local)),
+            du: DiskUsage::new(),
+            title: None,
+            picker: None,
+            chosen: vec![]
+        }
+    }
+}
+
+fn tab_bar(tabs: &[Tab], active_tab: usize, cd: &Path) -> String {
+    let mut output = String::new();
+    for (i, tab) in tabs.iter().enumerate() {
+        let cd = if i == active_tab { cd } else { &tab.cd };
+        let title = format!(" {}:{} ", i + 1, Tab::title(cd));
+        if i == active_tab {
+            output += &title.reverse().to_string();
+        } else {
+            output += &title;
+        }
+    }
+    output
+}
+
+fn draw_view(frame: &mut Frame, view: View, x: u16, width: u16) {
+    for i in 0..frame.height().saturating_sub(BOTTOM_RESERVED) {
+        let n = view.index + i as usize;
+        if n >= view.buffer.len() {
+            frame.put_str(x, i, "~", width);
+            continue;
+        }
+
+        let line = &view.buffer[n];
+        let distance = view.row.abs_diff(i);
+        let marker = match line.path() {
+            Some(path) if view.selected.contains(path) => '*',
+            _ => ' '
+        };
+        // Only the rows on screen are formatted, which is when entries
+        // whose type is not known yet (symlinks) get stat-ed.
+        let mut display = match (&view.header, line) {
+            (Some(header), _) if n == 0 => header.clone(),
+            (_, Line::Entry { entry, .. }) if view.desktop_names => match entry.desktop_name() {
+                Some(name) => format!("{}  \x1b[2m{}\x1b[0m", line.display(view.theme), name),
+                None => line.display(view.theme)
+            },
+            _ => line.display(view.theme)
+        };
+        if let (Some(du), Line::Entry { entry, .. }) = (view.du, line) {
+            display += &format!("  \x1b[2m{}\x1b[0m", du.label(&entry.path, view.apparent, view.dedup));
         }
+        frame.put_str(x, i, &format!("{distance:>3}{marker}"), LEFT_SIDE_PADDING);
+        frame.put_str(x + LEFT_SIDE_PADDING, i, &display, width.saturating_sub(LEFT_SIDE_PADDING));
+    }
+}
+
+fn draw_divider(frame: &mut Frame, x: u16) {
+    for i in 0..frame.height().saturating_sub(BOTTOM_RESERVED) {
+        frame.put_str(x, i, "│", 1);
+    }
+}
+
+fn compare_entries(key: SortKey, a: &Entry, b: &Entry, size: &dyn Fn(&Entry) -> Option<u64>) -> Ordering {
+    let by_name = || a.path.cmp(&b.path);
+    match key {
+        SortKey::Name => by_name(),
+        SortKey::Size => size(a).cmp(&size(b)).then_with(by_name),
+        SortKey::Mtime => b.metadata().and_then(|metadata| metadata.modified)
+            .cmp(&a.metadata().and_then(|metadata| metadata.modified))
+            .then_with(by_name),
+        SortKey::Ext => a.path.extension()
+            .cmp(&b.path.extension())
+            .then_with(by_name),
     }
 }
 
 impl CommandState {
     fn push(&mut self, digit: u32) {
         self.number = Some(match self.number {
-            Some(n) => n * 10 + digit as u16,
-            None => digit as u16
+            Some(n) => n.saturating_mul(10).saturating_add(digit as usize),
+            None => digit as usize
         });
     }
 }
@@ -848,12 +2287,100 @@ impl Display for Prefix {
     }
 }
 
-pub trait StoreEmpty {
-    fn append_empty(&mut self);
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    /// An app in `/home`, on a filesystem that lives in memory.
+    fn app() -> App {
+        let vfs = Memory::new();
+        vfs.write(Path::new("/home/notes.txt"), "notes").unwrap();
+        vfs.write(Path::new("/home/src/main.rs"), "").unwrap();
+        vfs.write(Path::new("/home/src/lib/mod.rs"), "").unwrap();
+
+        let mut app = App { vfs: Arc::new(vfs), cd: PathBuf::from("/home"), ..App::default() };
+        load(&mut app);
+        app
+    }
 
-impl StoreEmpty for Vec<(Option<PathBuf>, usize, String)> {
-    fn append_empty(&mut self) {
-        self.push((None, 1, " ".to_string()));
+    /// Reads `cd` again, and waits until all of it is in.
+    fn load(app: &mut App) {
+        app.generate_buffer();
+        while app.loader.is_some() {
+            std::thread::sleep(Duration::from_millis(1));
+            app.poll_loader();
+        }
+    }
+
+    /// The entries listed, each after its tree guide.
+    fn listed(app: &App) -> Vec<String> {
+        app.buffer.iter()
+            .filter_map(|line| match line {
+                Line::Entry { entry, guide } => Some(format!("{}{}", guide, entry.name)),
+                _ => None
+            })
+            .collect()
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn lists_cd() {
+        let app = app();
+        assert_eq!(listed(&app), ["src", "notes.txt"]);
+        assert!(app.status.is_empty());
+    }
+
+    #[test]
+    fn expands_dirs_in_place() {
+        let mut app = app();
+        app.tree.expand(PathBuf::from("/home/src"));
+        app.tree.expand(PathBuf::from("/home/src/lib"));
+        app.build_buffer();
+        assert_eq!(listed(&app), ["src", "├─ lib", "│  └─ mod.rs", "└─ main.rs", "notes.txt"]);
+
+        app.tree.collapse(Path::new("/home/src"));
+        app.build_buffer();
+        assert_eq!(listed(&app), ["src", "notes.txt"]);
+    }
+
+    #[test]
+    fn collapses_below_a_depth() {
+        let mut app = app();
+        app.tree.expand(PathBuf::from("/home/src"));
+        app.tree.expand(PathBuf::from("/home/src/lib"));
+
+        app.tree.collapse_below(Path::new("/home"), 1);
+        app.build_buffer();
+        assert_eq!(listed(&app), ["src", "├─ lib", "└─ main.rs", "notes.txt"]);
+
+        app.tree.collapse_below(Path::new("/home"), 0);
+        app.build_buffer();
+        assert_eq!(listed(&app), ["src", "notes.txt"]);
+    }
+
+    #[test]
+    fn flattens_the_tree_under_cd() {
+        let mut app = app();
+        app.flat = Some(2);
+        load(&mut app);
+        // Directories walked into give way to what is in them.
+        assert_eq!(listed(&app), ["src/lib", "notes.txt", "src/main.rs"]);
+    }
+
+    #[test]
+    fn goes_up_when_cd_is_removed() {
+        let mut app = app();
+        app.cd = PathBuf::from("/home/src/lib");
+        load(&mut app);
+        assert_eq!(listed(&app), ["mod.rs"]);
+
+        app.vfs.remove(Path::new("/home/src/lib")).unwrap();
+        app.reload_from_disk().unwrap();
+        while app.loader.is_some() {
+            std::thread::sleep(Duration::from_millis(1));
+            app.poll_loader();
+        }
+        assert_eq!(app.cd, Path::new("/home/src"));
+        assert_eq!(listed(&app), ["main.rs"]);
+    }
+}
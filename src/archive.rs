@@ -0,0 +1,844 @@
+use std::{
+    collections::{BTreeMap, HashMap}, fs::File, hash::{BuildHasher, Hasher, RandomState},
+    io::{self, BufReader, BufWriter, PipeReader, Read, Write}, path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex}, thread::{self, JoinHandle}, time::{Duration, SystemTime, UNIX_EPOCH}
+};
+
+use crate::vfs::{DirEntry, Kind, Listing, Stat, Vfs};
+
+/// The archives that can be browsed like directories, told apart by the
+/// suffix of their name.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Zip,
+    Tar,
+    TarGz,
+    TarXz,
+    TarZst,
+}
+
+const SUFFIXES: &[(&str, Format)] = &[
+    (".zip", Format::Zip),
+    (".tar", Format::Tar),
+    (".tar.gz", Format::TarGz),
+    (".tgz", Format::TarGz),
+    (".tar.xz", Format::TarXz),
+    (".txz", Format::TarXz),
+    (".tar.zst", Format::TarZst),
+    (".tzst", Format::TarZst),
+];
+
+/// A file or directory inside an archive.
+#[derive(Clone)]
+pub struct Member {
+    /// Relative to the root of the archive.
+    pub path: PathBuf,
+    pub stat: Stat,
+}
+
+impl Format {
+    pub fn of(path: &Path) -> Option<Format> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        SUFFIXES.iter()
+            .find(|(suffix, _)| name.ends_with(suffix))
+            .map(|(_, format)| *format)
+    }
+}
+
+/// The name of `archive` without its suffix, e.g. `proj` for `proj.tar.gz`.
+fn stem(archive: &Path) -> String {
+    let name = archive.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let suffix = SUFFIXES.iter()
+        .find(|(suffix, _)| name.to_lowercase().ends_with(suffix))
+        .map_or(0, |(suffix, _)| suffix.len());
+    name[..name.len() - suffix].to_string()
+}
+
+/// Whether `path` is an archive on `vfs` that can be entered.
+pub fn is_archive(vfs: &dyn Vfs, path: &Path) -> bool {
+    Format::of(path).is_some() && vfs.stat(path).is_ok_and(|stat| !stat.is_dir)
+}
+
+/// Splits a path that goes through an archive on `vfs`, e.g. `/a/b.zip/c/d`,
+/// into the archive and the path inside it, `c/d`. Paths on the filesystem
+/// give `None`.
+pub fn split(vfs: &dyn Vfs, path: &Path) -> Option<(PathBuf, PathBuf)> {
+    let archive = path.ancestors().find(|ancestor| is_archive(vfs, ancestor))?;
+    let inner = path.strip_prefix(archive).ok()?;
+    Some((archive.to_path_buf(), inner.to_path_buf()))
+}
+
+/// Browses the archives on the local filesystem as directories, leaving
+/// every other path to the backend it wraps. Archives are read-only, and
+/// the files inside them are never run.
+pub struct Archives<V: Vfs> {
+    pub inner: V,
+    /// The members of the archives read so far.
+    members: Mutex<HashMap<PathBuf, ReadMembers>>,
+}
+
+/// The members of an archive, and when it was modified as they were read.
+type ReadMembers = (SystemTime, Arc<Vec<Member>>);
+
+impl<V: Vfs> Archives<V> {
+    pub fn new(inner: V) -> Archives<V> {
+        Archives { inner, members: Mutex::new(HashMap::new()) }
+    }
+
+    /// The members of `archive`, which is only read again once it changed.
+    fn members(&self, archive: &Path) -> io::Result<Arc<Vec<Member>>> {
+        let modified = std::fs::metadata(archive)?.modified().ok();
+        if let Some((read_at, members)) = self.members.lock().unwrap().get(archive) {
+            if Some(*read_at) == modified {
+                return Ok(members.clone());
+            }
+        }
+
+        let members = Arc::new(members(archive)?);
+        if let Some(modified) = modified {
+            self.members.lock().unwrap().insert(archive.to_path_buf(), (modified, members.clone()));
+        }
+        Ok(members)
+    }
+}
+
+impl<V: Vfs> Vfs for Archives<V> {
+    fn list(&self, dir: &Path) -> io::Result<Listing> {
+        let (archive, inner) = match split(&self.inner, dir) {
+            Some(split) => split,
+            None => return self.inner.list(dir)
+        };
+
+        // Archives are read in one go, so there is nothing to gain from
+        // reading them lazily.
+        let dir = dir.to_path_buf();
+        let members = self.members(&archive)?;
+        let entries = list(&members, &archive, &inner)?.into_iter().filter_map(move |member| {
+            Some(Ok(DirEntry {
+                path: dir.join(member.path.file_name()?),
+                kind: Some(if member.stat.is_dir { Kind::Dir } else { Kind::File }),
+                stat: Some(member.stat),
+            }))
+        });
+        Ok(Box::new(entries.collect::<Vec<_>>().into_iter()))
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Stat> {
+        let (archive, member) = match split_member(&self.inner, path) {
+            Some(split) => split,
+            None => return self.inner.stat(path)
+        };
+        let members = self.members(&archive)?;
+        list(&members, &archive, member.parent().unwrap_or(Path::new("")))?
+            .into_iter()
+            .find(|candidate| candidate.path == member)
+            .map(|member| member.stat)
+            .ok_or_else(|| io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no {} in {}", member.display(), archive.display())
+            ))
+    }
+
+    fn symlink_stat(&self, path: &Path) -> io::Result<Stat> {
+        if split_member(&self.inner, path).is_some() {
+            return self.stat(path);
+        }
+        self.inner.symlink_stat(path)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        if split_member(&self.inner, path).is_some() {
+            return Ok(Box::new(File::open(local_copy(&self.inner, path)?)?));
+        }
+        self.inner.open(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        writable(&self.inner, &[from, to])?;
+        self.inner.rename(from, to)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        writable(&self.inner, &[from, to])?;
+        self.inner.copy(from, to)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        writable(&self.inner, &[path])?;
+        self.inner.remove(path)
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        writable(&self.inner, &[path])?;
+        self.inner.create_dir(path)
+    }
+}
+
+/// Same as `split`, except for the archive itself, which is a file as far
+/// as its own directory goes.
+fn split_member(vfs: &dyn Vfs, path: &Path) -> Option<(PathBuf, PathBuf)> {
+    split(vfs, path).filter(|(_, member)| !member.as_os_str().is_empty())
+}
+
+fn writable(vfs: &dyn Vfs, paths: &[&Path]) -> io::Result<()> {
+    if paths.iter().any(|path| split_member(vfs, path).is_some()) {
+        return Err(io::Error::new(io::ErrorKind::ReadOnlyFilesystem, "archives are read-only"));
+    }
+    Ok(())
+}
+
+/// Lists the members right inside `dir`, a path inside the archive at
+/// `archive`, out of all of its `members`.
+fn list(members: &[Member], archive: &Path, dir: &Path) -> io::Result<Vec<Member>> {
+    if !dir.as_os_str().is_empty() && !members.iter().any(|member| member.path == dir && member.stat.is_dir) {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no directory {} in {}", dir.display(), archive.display())
+        ));
+    }
+
+    Ok(members.iter().filter(|member| member.path.parent() == Some(dir)).cloned().collect())
+}
+
+/// Lists every member of the archive at `path`, including the directories
+/// that are only implied by the paths of the files inside them.
+pub fn members(path: &Path) -> io::Result<Vec<Member>> {
+    let format = Format::of(path).ok_or_else(|| invalid("not an archive"))?;
+
+    // Keyed by path, so that members added twice to a tar show up once.
+    let mut members = BTreeMap::new();
+    if format == Format::Zip {
+        let mut archive = zip::ZipArchive::new(BufReader::new(File::open(path)?))?;
+        for i in 0..archive.len() {
+            let file = archive.by_index_raw(i)?;
+            if let Some(path) = clean(Path::new(file.name())) {
+                members.insert(path, member_stat(file.is_dir(), file.size(), file.last_modified().and_then(zip_time)));
+            }
+        }
+    } else {
+        let mut archive = tar::Archive::new(open_tar(path, format)?);
+        for entry in archive.entries()? {
+            let entry = entry?;
+            let header = entry.header();
+            if let Some(path) = clean(&entry.path()?) {
+                let modified = header.mtime().ok().map(|mtime| UNIX_EPOCH + Duration::from_secs(mtime));
+                members.insert(path, member_stat(header.entry_type().is_dir(), header.size().unwrap_or(0), modified));
+            }
+        }
+    }
+
+    let implied: Vec<PathBuf> = members.keys()
+        .flat_map(|path| path.ancestors().skip(1))
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map(Path::to_path_buf)
+        .collect();
+    for dir in implied {
+        members.entry(dir).or_insert(member_stat(true, 0, None));
+    }
+
+    Ok(members.into_iter().map(|(path, stat)| Member { path, stat }).collect())
+}
+
+/// Members take no space on disk of their own, and are never run.
+fn member_stat(is_dir: bool, len: u64, modified: Option<SystemTime>) -> Stat {
+    Stat { is_dir, len, modified, executable: false, disk_len: 0, links: 1, id: None }
+}
+
+/// Writes the file at `member`, a path inside the archive at `archive`,
+/// to `dest`, which must not exist yet.
+pub fn extract_member(archive: &Path, member: &Path, dest: &Path) -> io::Result<()> {
+    let format = Format::of(archive).ok_or_else(|| invalid("not an archive"))?;
+
+    if format == Format::Zip {
+        let mut zip = zip::ZipArchive::new(BufReader::new(File::open(archive)?))?;
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i)?;
+            if !file.is_dir() && clean(Path::new(file.name())).as_deref() == Some(member) {
+                io::copy(&mut file, &mut create_new(dest)?)?;
+                return Ok(());
+            }
+        }
+    } else {
+        let mut tar = tar::Archive::new(open_tar(archive, format)?);
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            if entry.header().entry_type().is_file() && clean(&entry.path()?).as_deref() == Some(member) {
+                io::copy(&mut entry, &mut create_new(dest)?)?;
+                return Ok(());
+            }
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no file {} in {}", member.display(), archive.display())
+    ))
+}
+
+/// Extracts the archive at `archive` into `dest`. When everything in it is
+/// inside a single directory, that directory lands in `dest`; otherwise
+/// the contents go in a new directory named after the archive. Existing
+/// files are never overwritten: the directory gets a numbered name
+/// instead. `progress` is told how many of the files are done. Returns the
+/// directory that was created, and how many hard links were left out
+/// because what they link to is not in it.
+pub fn extract(archive: &Path, dest: &Path, progress: &dyn Fn(usize, usize)) -> io::Result<(PathBuf, usize)> {
+    let format = Format::of(archive).ok_or_else(|| invalid("not an archive"))?;
+    let members = members(archive)?;
+    let total = members.iter().filter(|member| !member.stat.is_dir).count();
+
+    let mut roots = members.iter().filter(|member| member.path.parent() == Some(Path::new("")));
+    let (strip, name) = match (roots.next(), roots.next()) {
+        (Some(root), None) if root.stat.is_dir => (root.path.clone(), root.path.to_string_lossy().to_string()),
+        _ => (PathBuf::new(), stem(archive))
+    };
+    let root = unique_path(&dest.join(name));
+    std::fs::create_dir_all(&root)?;
+
+    let target = |path: &Path| -> Option<PathBuf> {
+        let path = clean(path)?;
+        Some(root.join(path.strip_prefix(&strip).ok()?))
+    };
+
+    let (mut done, mut skipped) = (0, 0);
+    if format == Format::Zip {
+        let mut zip = zip::ZipArchive::new(BufReader::new(File::open(archive)?))?;
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i)?;
+            let out = match target(Path::new(file.name())) {
+                Some(out) => out,
+                None => continue
+            };
+            if file.is_dir() {
+                std::fs::create_dir_all(&out)?;
+                continue;
+            }
+
+            if let Some(parent) = out.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            io::copy(&mut file, &mut File::create(&out)?)?;
+            #[cfg(unix)]
+            if let Some(mode) = file.unix_mode() {
+                use std::os::unix::fs::PermissionsExt;
+                // Without setuid, setgid and sticky bits, as tar does.
+                std::fs::set_permissions(&out, std::fs::Permissions::from_mode(mode & 0o777))?;
+            }
+
+            done += 1;
+            progress(done, total);
+        }
+    } else {
+        // Hard links are made last, as what they link to may come later.
+        let mut links = vec![];
+        let mut tar = tar::Archive::new(open_tar(archive, format)?);
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            let out = match target(&entry.path()?) {
+                Some(out) => out,
+                None => continue
+            };
+            // A symlink unpacked earlier must not lead outside of root.
+            if through_symlink(&root, &out) {
+                continue;
+            }
+            if let Some(parent) = out.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            match entry.header().entry_type() {
+                // Names a member of the archive, which `unpack` would look
+                // for from the current directory instead.
+                tar::EntryType::Link => {
+                    if let Some(original) = entry.link_name()?.as_deref().and_then(target) {
+                        links.push((original, out));
+                    }
+                    continue;
+                },
+                tar::EntryType::Symlink => {
+                    match entry.link_name()? {
+                        Some(link) if leads_under(&root, &out, &link) => entry.unpack(&out)?,
+                        _ => continue
+                    };
+                },
+                // Takes care of permissions and times.
+                _ => { entry.unpack(&out)?; }
+            }
+
+            if !entry.header().entry_type().is_dir() {
+                done += 1;
+                progress(done, total);
+            }
+        }
+
+        for (original, out) in links {
+            if through_symlink(&root, &original) || through_symlink(&root, &out) {
+                continue;
+            }
+            if std::fs::hard_link(&original, &out).is_err() {
+                skipped += 1;
+                continue;
+            }
+            done += 1;
+            progress(done, total);
+        }
+    }
+
+    Ok((root, skipped))
+}
+
+/// Creates the archive `dest`, in the format its name asks for, holding
+/// `sources` and everything inside them. `progress` is told how many of
+/// the files are done. Returns how many went in.
+pub fn create(dest: &Path, sources: &[PathBuf], progress: &dyn Fn(usize, usize)) -> io::Result<usize> {
+    let format = Format::of(dest).ok_or_else(|| invalid(
+        "the name must end in .zip, .tar, .tar.gz, .tar.xz or .tar.zst"
+    ))?;
+    if dest.symlink_metadata().is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", dest.display())));
+    }
+
+    // Every file and directory, with the name it gets in the archive.
+    let mut files = vec![];
+    for source in sources {
+        let name = PathBuf::from(source.file_name().unwrap_or_default());
+        walk(source, &name, dest, &mut files)?;
+    }
+    let total = files.len();
+
+    let file = File::create(dest)?;
+    let result = match format {
+        Format::Zip => write_zip(file, &files, progress),
+        Format::Tar => write_tar(BufWriter::new(file), &files, progress),
+        Format::TarGz => {
+            let encoder = flate2::write::GzEncoder::new(BufWriter::new(file), flate2::Compression::default());
+            write_tar(encoder, &files, progress)
+        },
+        Format::TarXz | Format::TarZst => {
+            // Both encoders read their input, so the tar is written into a
+            // pipe that they read from on a thread of their own.
+            let (reader, writer) = io::pipe()?;
+            let compressor = thread::spawn(move || -> io::Result<()> {
+                let mut reader = BufReader::new(reader);
+                let mut file = BufWriter::new(file);
+                if format == Format::TarXz {
+                    lzma_rs::xz_compress(&mut reader, &mut file)?;
+                } else {
+                    ruzstd::encoding::compress(&mut reader, &mut file, ruzstd::encoding::CompressionLevel::Fastest);
+                }
+                file.flush()
+            });
+            let written = write_tar(writer, &files, progress);
+            let compressed = compressor.join()
+                .unwrap_or_else(|_| Err(io::Error::other("the compressor panicked")));
+            written.and(compressed)
+        }
+    };
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(dest);
+    }
+    result.map(|_| total)
+}
+
+/// Collects `path`, and everything inside it if it is a directory, leaving
+/// out `skip`, the archive being written.
+fn walk(path: &Path, name: &Path, skip: &Path, files: &mut Vec<(PathBuf, PathBuf)>) -> io::Result<()> {
+    if path == skip {
+        return Ok(());
+    }
+    files.push((path.to_path_buf(), name.to_path_buf()));
+
+    let metadata = path.symlink_metadata()?;
+    if metadata.is_dir() {
+        let mut children: Vec<_> = std::fs::read_dir(path)?.collect::<io::Result<_>>()?;
+        children.sort_by_key(|child| child.file_name());
+        for child in children {
+            walk(&child.path(), &name.join(child.file_name()), skip, files)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_zip(file: File, files: &[(PathBuf, PathBuf)], progress: &dyn Fn(usize, usize)) -> io::Result<()> {
+    let mut zip = zip::ZipWriter::new(BufWriter::new(file));
+    for (i, (path, name)) in files.iter().enumerate() {
+        let metadata = std::fs::metadata(path)?;
+        let mut options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            options = options.unix_permissions(metadata.permissions().mode());
+        }
+        if let Some(time) = metadata.modified().ok().and_then(to_zip_time) {
+            options = options.last_modified_time(time);
+        }
+
+        let name = name.to_string_lossy();
+        if metadata.is_dir() {
+            zip.add_directory(name, options)?;
+        } else {
+            zip.start_file(name, options)?;
+            io::copy(&mut File::open(path)?, &mut zip)?;
+        }
+        progress(i + 1, files.len());
+    }
+    zip.finish()?.flush()?;
+
+    Ok(())
+}
+
+fn write_tar(out: impl Write, files: &[(PathBuf, PathBuf)], progress: &dyn Fn(usize, usize)) -> io::Result<()> {
+    let mut tar = tar::Builder::new(out);
+    tar.follow_symlinks(false);
+    for (i, (path, name)) in files.iter().enumerate() {
+        tar.append_path_with_name(path, name)?;
+        progress(i + 1, files.len());
+    }
+    tar.into_inner()?.flush()?;
+
+    Ok(())
+}
+
+/// Whether any directory between `root` and `path` is a symlink.
+fn through_symlink(root: &Path, path: &Path) -> bool {
+    path.ancestors()
+        .skip(1)
+        .take_while(|dir| *dir != root)
+        .any(|dir| dir.symlink_metadata().is_ok_and(|metadata| metadata.is_symlink()))
+}
+
+/// Whether the symlink at `link`, somewhere under `root`, leads to a path
+/// under `root` as well when it points to `target`. The target may only
+/// climb up before going down: a `..` further on could follow another
+/// symlink out.
+fn leads_under(root: &Path, link: &Path, target: &Path) -> bool {
+    let mut depth = link.parent()
+        .and_then(|dir| dir.strip_prefix(root).ok())
+        .map_or(0, |dir| dir.components().count());
+    let mut down = false;
+    for component in target.components() {
+        match component {
+            Component::ParentDir if !down && depth > 0 => depth -= 1,
+            Component::Normal(_) => down = true,
+            Component::CurDir => (),
+            _ => return false
+        }
+    }
+    true
+}
+
+/// `path`, or when it exists, `path` with the first free number appended.
+fn unique_path(path: &Path) -> PathBuf {
+    let mut unique = path.to_path_buf();
+    let mut n = 1;
+    while unique.symlink_metadata().is_ok() {
+        unique = PathBuf::from(format!("{}-{}", path.display(), n));
+        n += 1;
+    }
+    unique
+}
+
+/// Returns a path on the filesystem with the contents of `path`: `path`
+/// itself, or for a file inside an archive, a copy extracted to a
+/// temporary directory so that other programs can open it.
+pub fn local_copy(vfs: &dyn Vfs, path: &Path) -> io::Result<PathBuf> {
+    let (archive, member) = match split(vfs, path) {
+        Some(split) => split,
+        None => return Ok(path.to_path_buf())
+    };
+
+    let dest = temp_dir()?
+        .join(archive.file_name().unwrap_or_default())
+        .join(&member);
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // Extracted again, in case the archive changed since.
+    match std::fs::remove_file(&dest) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => ()
+    }
+    extract_member(&archive, &member, &dest)?;
+
+    Ok(dest)
+}
+
+/// The directory that `local_copy` extracts to, once it was made.
+static TEMP_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Where the files opened from inside archives are extracted to. Made the
+/// first time it is needed, like `mkdtemp` does: under a name that cannot
+/// be guessed, for this user only, and never one that someone else made
+/// beforehand.
+fn temp_dir() -> io::Result<PathBuf> {
+    let mut temp_dir = TEMP_DIR.lock().unwrap();
+    if let Some(dir) = &*temp_dir {
+        return Ok(dir.clone());
+    }
+
+    let random = RandomState::new().build_hasher().finish();
+    let dir = std::env::temp_dir().join(format!("fap-{}-{:016x}", std::process::id(), random));
+    let mut builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(&dir)?;
+
+    *temp_dir = Some(dir.clone());
+    Ok(dir)
+}
+
+/// Removes the files extracted by `local_copy`, once nothing needs them
+/// anymore.
+pub fn remove_temp_dir() {
+    if let Some(dir) = TEMP_DIR.lock().ok().and_then(|mut dir| dir.take()) {
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
+
+/// Creates the file at `path`, failing when anything is there already,
+/// be it a symlink.
+fn create_new(path: &Path) -> io::Result<File> {
+    let mut options = File::options();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::custom_flags(&mut options, libc::O_NOFOLLOW);
+    options.open(path)
+}
+
+/// Reads the tar that the xz decoder thread writes into a pipe. Once the
+/// pipe is drained, fails with the decoder's error if it stopped on one,
+/// rather than handing over what was decoded until then as the whole tar.
+struct XzReader {
+    pipe: PipeReader,
+    decoder: Option<JoinHandle<lzma_rs::error::Result<()>>>,
+}
+
+impl Read for XzReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.pipe.read(buf)?;
+        if read == 0 && !buf.is_empty() {
+            match self.decoder.take().map(JoinHandle::join) {
+                Some(Ok(Err(e))) => return Err(invalid(&format!("corrupt xz data: {}", e))),
+                Some(Err(_)) => return Err(io::Error::other("the xz decoder panicked")),
+                Some(Ok(Ok(()))) | None => ()
+            }
+        }
+        Ok(read)
+    }
+}
+
+/// Opens the tar at `path`, decompressing it on the fly.
+fn open_tar(path: &Path, format: Format) -> io::Result<Box<dyn Read + Send>> {
+    let file = BufReader::new(File::open(path)?);
+    match format {
+        Format::TarGz => Ok(Box::new(flate2::bufread::MultiGzDecoder::new(file))),
+        Format::TarZst => {
+            let decoder = ruzstd::decoding::StreamingDecoder::new(file)
+                .map_err(|e| invalid(&e.to_string()))?;
+            Ok(Box::new(decoder))
+        },
+        Format::TarXz => {
+            // lzma-rs only decompresses into a writer, so it does so on a
+            // thread of its own, writing into a pipe that tar reads from.
+            let (pipe, mut writer) = io::pipe()?;
+            let mut file = file;
+            let decoder = thread::spawn(move || lzma_rs::xz_decompress(&mut file, &mut writer));
+            Ok(Box::new(XzReader { pipe, decoder: Some(decoder) }))
+        },
+        Format::Tar | Format::Zip => Ok(Box::new(file))
+    }
+}
+
+/// Makes the path of a member relative to the root of the archive. Paths
+/// that would escape it, through `..`, are left out.
+fn clean(path: &Path) -> Option<PathBuf> {
+    let mut cleaned = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => cleaned.push(name),
+            Component::ParentDir => return None,
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => ()
+        }
+    }
+    (!cleaned.as_os_str().is_empty()).then_some(cleaned)
+}
+
+/// Converts the MS-DOS time of a zip member, taken as UTC.
+fn zip_time(time: zip::DateTime) -> Option<SystemTime> {
+    // Days from the civil date, after Howard Hinnant's days_from_civil.
+    let (month, day) = (time.month() as i64, time.day() as i64);
+    let year = time.year() as i64 - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    let seconds = days * 86_400
+        + time.hour() as i64 * 3600
+        + time.minute() as i64 * 60
+        + time.second() as i64;
+    Some(UNIX_EPOCH + Duration::from_secs(u64::try_from(seconds).ok()?))
+}
+
+/// The inverse of `zip_time`, after Howard Hinnant's civil_from_days.
+fn to_zip_time(time: SystemTime) -> Option<zip::DateTime> {
+    let seconds = time.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64;
+    let (days, seconds) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    zip::DateTime::from_date_and_time(
+        u16::try_from(year).ok()?, month as u8, day as u8,
+        (seconds / 3600) as u8, (seconds / 60 % 60) as u8, (seconds % 60) as u8
+    ).ok()
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+    use crate::vfs::Local;
+
+    /// A tar holding `pkg/a.txt`, hard links to it and to a file that comes
+    /// later or not at all, and links that try to lead out of where the tar
+    /// is extracted.
+    fn links_tar(path: &Path) -> io::Result<()> {
+        let mut tar = tar::Builder::new(File::create(path)?);
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_mode(0o755);
+        header.set_size(0);
+        tar.append_data(&mut header, "pkg", io::empty())?;
+
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(0o644);
+        header.set_size(5);
+        tar.append_data(&mut header, "pkg/a.txt", &b"hello"[..])?;
+
+        for (kind, name, link) in [
+            (tar::EntryType::Link, "pkg/b.txt", "pkg/a.txt"),
+            (tar::EntryType::Link, "pkg/early.txt", "pkg/late.txt"),
+            (tar::EntryType::Link, "pkg/gone.txt", "pkg/missing.txt"),
+            (tar::EntryType::Link, "pkg/parent", "../outside.txt"),
+            (tar::EntryType::Symlink, "pkg/c.txt", "a.txt"),
+            (tar::EntryType::Symlink, "pkg/up", "../.."),
+            (tar::EntryType::Symlink, "pkg/abs", "/etc/passwd"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(kind);
+            header.set_size(0);
+            tar.append_link(&mut header, name, link)?;
+        }
+
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(0o644);
+        header.set_size(4);
+        tar.append_data(&mut header, "pkg/late.txt", &b"late"[..])?;
+        tar.finish()?;
+        Ok(())
+    }
+
+    #[test]
+    fn extracts_links_inside_the_root_only() {
+        let dir = std::env::temp_dir().join(format!("fap-test-links-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("outside.txt"), "outside").unwrap();
+        let archive = dir.join("links.tar");
+        links_tar(&archive).unwrap();
+
+        let (root, skipped) = extract(&archive, &dir, &|_, _| ()).unwrap();
+        let read = |name: &str| std::fs::read_to_string(root.join(name)).ok();
+        let exists = |name: &str| root.join(name).symlink_metadata().is_ok();
+        assert_eq!(root, dir.join("pkg"));
+        assert_eq!(read("a.txt").as_deref(), Some("hello"));
+        assert_eq!(read("b.txt").as_deref(), Some("hello"));
+        assert_eq!(read("c.txt").as_deref(), Some("hello"));
+        assert_eq!(read("early.txt").as_deref(), Some("late"));
+        assert!(!exists("gone.txt"));
+        assert_eq!(skipped, 1);
+        assert!(!exists("parent"));
+        assert!(!exists("up"));
+        assert!(!exists("abs"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn extracts_zip_files_without_special_mode_bits() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("fap-test-modes-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("tools.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&archive).unwrap());
+        zip.start_file("tools/run", zip::write::SimpleFileOptions::default().unix_permissions(0o4755)).unwrap();
+        zip.write_all(b"#!/bin/sh\n").unwrap();
+        zip.finish().unwrap();
+
+        let (root, _) = extract(&archive, &dir, &|_, _| ()).unwrap();
+        let mode = std::fs::metadata(root.join("run")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o7777, 0o755);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn opens_members_from_a_private_dir() {
+        let dir = std::env::temp_dir().join(format!("fap-test-open-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("notes.tar");
+        let mut tar = tar::Builder::new(File::create(&archive).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(0o644);
+        header.set_size(5);
+        tar.append_data(&mut header, "docs/a.txt", &b"hello"[..]).unwrap();
+        tar.finish().unwrap();
+        drop(tar);
+
+        let copy = local_copy(&Local, &archive.join("docs/a.txt")).unwrap();
+        // Opened a second time, the copy is made again.
+        assert_eq!(local_copy(&Local, &archive.join("docs/a.txt")).unwrap(), copy);
+        assert_eq!(std::fs::read_to_string(&copy).unwrap(), "hello");
+
+        let temp_dir = temp_dir().unwrap();
+        assert!(copy.starts_with(&temp_dir));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&temp_dir).unwrap().permissions().mode() & 0o777, 0o700);
+        }
+
+        remove_temp_dir();
+        assert!(!temp_dir.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn finds_archives_through_the_vfs() {
+        let vfs = Memory::new();
+        vfs.write(Path::new("/home/a.zip"), "").unwrap();
+        vfs.write(Path::new("/home/b.zip/notes.txt"), "").unwrap();
+
+        assert!(is_archive(&vfs, Path::new("/home/a.zip")));
+        assert!(!is_archive(&vfs, Path::new("/home/b.zip")));
+        assert_eq!(split(&vfs, Path::new("/home/a.zip/c/d")),
+            Some((PathBuf::from("/home/a.zip"), PathBuf::from("c/d"))));
+        assert_eq!(split(&vfs, Path::new("/home/b.zip/notes.txt")), None);
+    }
+}
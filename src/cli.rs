@@ -0,0 +1,205 @@
+use std::path::PathBuf;
+
+use crate::{picker::Select, settings::SortKey};
+
+pub const USAGE: &str = "\
+usage: fap [OPTIONS] [DIR]
+       fap --choose-files <FILE> [PICKER OPTIONS] [DIR]
+       fap --init <bash|zsh|fish|nushell> [--bind]
+
+Opens a file explorer in DIR, or in the current directory. The directory
+it is left in with Space is printed on stdout, or written to the file given
+by --cwd-file. Exits with 1 when left with Esc or :q instead.
+
+Options:
+  -a, --show-hidden       show hidden files
+  -s, --sort <KEY>        sort by name, size, mtime or ext
+      --cwd-file <FILE>   write the chosen directory to FILE instead of stdout
+      --config <FILE>     read settings from FILE instead of ~/.config/fap/config.toml
+      --theme <THEME>     color theme: default, light, mono, or a theme file
+      --no-color          draw without colors (also set by NO_COLOR)
+      --title <TEXT>      show TEXT at the top of the screen
+      --init <SHELL>      print the shell wrapper that lets fap change directory
+  -V, --version           print the version
+  -h, --help              print this help
+
+Picker options:
+      --choose-files <FILE>
+                          pick entries with Enter or Space (marked ones with v)
+                          and write their paths to FILE, or stdout for -
+      --choose-dir <FILE> same as --cwd-file
+      --print0            separate picked paths with NUL instead of newlines
+      --select <KIND>     what can be picked: files (default), dirs or any
+      --ext <EXT,...>     only list files with one of these extensions
+      --glob <PATTERN>    only list files whose name matches PATTERN";
+
+/// What fap was asked to do on the command line.
+pub enum Invocation {
+    Run(Box<Options>),
+    Init { shell: String, bind: bool },
+    Help,
+    Version,
+}
+
+#[derive(Default)]
+pub struct Options {
+    pub dir: Option<PathBuf>,
+    pub show_hidden: bool,
+    pub sort: Option<SortKey>,
+    pub cwd_file: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    pub theme: Option<String>,
+    pub no_color: bool,
+    pub title: Option<String>,
+    pub choose_files: Option<PathBuf>,
+    pub print0: bool,
+    pub select: Option<Select>,
+    pub extensions: Vec<String>,
+    pub glob: Option<glob::Pattern>,
+}
+
+/// Parses the arguments, without the program name. Errors are usage
+/// errors, meant to be printed alongside `USAGE`.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Invocation, String> {
+    let mut args = args.into_iter();
+    let mut options = Options::default();
+    let mut only_positional = false;
+
+    while let Some(arg) = args.next() {
+        if only_positional || arg == "-" || !arg.starts_with('-') {
+            if options.dir.is_some() {
+                return Err(format!("unexpected argument: {}", arg));
+            }
+            options.dir = Some(PathBuf::from(arg));
+            continue;
+        }
+
+        // Long options take their value either as `--opt=value` or as the
+        // next argument.
+        let (name, inline) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None)
+        };
+        let takes_value = matches!(name.as_str(), "-s" | "--sort" | "--cwd-file" | "--config" | "--theme" | "--init"
+            | "--title" | "--choose-files" | "--choose-dir" | "--select" | "--ext" | "--glob");
+        if inline.is_some() && !takes_value {
+            return Err(format!("{} does not take a value", name));
+        }
+        let value = |args: &mut dyn Iterator<Item = String>| {
+            inline.clone().or_else(|| args.next()).ok_or(format!("{} needs a value", name))
+        };
+
+        match name.as_str() {
+            "--" => only_positional = true,
+            "-a" | "--show-hidden" => options.show_hidden = true,
+            "-s" | "--sort" => {
+                let key = value(&mut args)?;
+                options.sort = Some(SortKey::parse(&key).map_err(|e| e.to_string())?);
+            },
+            "--cwd-file" => options.cwd_file = Some(PathBuf::from(value(&mut args)?)),
+            "--config" => options.config = Some(PathBuf::from(value(&mut args)?)),
+            "--theme" => options.theme = Some(value(&mut args)?),
+            "--no-color" => options.no_color = true,
+            "--title" => options.title = Some(value(&mut args)?),
+            "--choose-files" => options.choose_files = Some(PathBuf::from(value(&mut args)?)),
+            "--choose-dir" => options.cwd_file = Some(PathBuf::from(value(&mut args)?)),
+            "--print0" => options.print0 = true,
+            "--select" => {
+                let kind = value(&mut args)?;
+                options.select = Some(Select::parse(&kind).ok_or(format!(
+                    "unknown kind: {} (expected one of {})", kind, Select::ALL.join(", ")
+                ))?);
+            },
+            "--ext" => options.extensions.extend(value(&mut args)?
+                .split(',')
+                .map(|extension| extension.trim_start_matches('.').to_string())
+                .filter(|extension| !extension.is_empty())
+            ),
+            "--glob" => {
+                let pattern = value(&mut args)?;
+                options.glob = Some(glob::Pattern::new(&pattern).map_err(|e| {
+                    format!("invalid pattern {}: {}", pattern, e.msg)
+                })?);
+            },
+            "--init" => {
+                let shell = value(&mut args)?;
+                let bind = match args.next().as_deref() {
+                    Some("--bind") => true,
+                    Some(arg) => return Err(format!("unexpected argument: {}", arg)),
+                    None => false
+                };
+                return Ok(Invocation::Init { shell, bind });
+            },
+            "-V" | "--version" => return Ok(Invocation::Version),
+            "-h" | "--help" => return Ok(Invocation::Help),
+            _ => return Err(format!("unknown option: {}", arg))
+        }
+    }
+
+    Ok(Invocation::Run(Box::new(options)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Invocation, String> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn options(args: &[&str]) -> Options {
+        match parse_args(args) {
+            Ok(Invocation::Run(options)) => *options,
+            _ => panic!("{:?} did not parse to options", args)
+        }
+    }
+
+    fn error(args: &[&str]) -> String {
+        match parse_args(args) {
+            Err(e) => e,
+            Ok(_) => panic!("{:?} parsed", args)
+        }
+    }
+
+    #[test]
+    fn parses_sort_keys() {
+        assert!(options(&["-s", "size"]).sort == Some(SortKey::Size));
+        assert!(options(&["--sort=mtime"]).sort == Some(SortKey::Mtime));
+        assert!(options(&["--sort", "ext", "dir"]).sort == Some(SortKey::Ext));
+        assert!(options(&[]).sort.is_none());
+        assert!(parse_args(&["--sort=weight"]).is_err());
+    }
+
+    #[test]
+    fn parses_options_and_the_dir() {
+        let options = options(&["-a", "--title=two words", "--ext", ".rs,toml,", "--", "-dir"]);
+        assert!(options.show_hidden);
+        assert_eq!(options.title.as_deref(), Some("two words"));
+        assert_eq!(options.extensions, ["rs", "toml"]);
+        assert_eq!(options.dir, Some(PathBuf::from("-dir")));
+    }
+
+    #[test]
+    fn parses_init() {
+        assert!(matches!(parse_args(&["--init", "bash"]),
+            Ok(Invocation::Init { shell, bind: false }) if shell == "bash"));
+        assert!(matches!(parse_args(&["--init=zsh", "--bind"]),
+            Ok(Invocation::Init { shell, bind: true }) if shell == "zsh"));
+        assert_eq!(error(&["--init", "fish", "--bund"]), "unexpected argument: --bund");
+    }
+
+    #[test]
+    fn rejects_bad_options() {
+        assert_eq!(error(&["--frobnicate"]), "unknown option: --frobnicate");
+        assert_eq!(error(&["-x"]), "unknown option: -x");
+        assert_eq!(error(&["--no-color=yes"]), "--no-color does not take a value");
+        assert_eq!(error(&["a", "b"]), "unexpected argument: b");
+    }
+
+    #[test]
+    fn rejects_missing_values() {
+        assert_eq!(error(&["-s"]), "-s needs a value");
+        assert_eq!(error(&["--cwd-file"]), "--cwd-file needs a value");
+        assert_eq!(error(&["--init"]), "--init needs a value");
+    }
+}
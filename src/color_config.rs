@@ -1,16 +1,137 @@
-use crossterm::style::Color; 
-
-#[allow(dead_code)]
-struct ColorConfig {
-    dir_name_color: Color,
-    forward_slash_color: Color,
-    file_name_color: Color,
-    background_color: Color,
+use std::{io, path::Path};
+
+use crossterm::style::{Color, ContentStyle};
+
+/// Names of the built-in themes, accepted by `--theme`.
+pub const THEMES: &[&str] = &["default", "light", "mono"];
+
+/// Colors used to draw the listing. `Color::Reset` leaves the terminal's
+/// own color, and a theme made only of it draws no escape codes at all.
+pub struct ColorConfig {
+    pub dir_name_color: Color,
+    pub forward_slash_color: Color,
+    /// Color of the slash after `..` and `.`.
+    pub link_slash_color: Color,
+    pub file_name_color: Color,
+    pub background_color: Color,
 }
 
-#[allow(dead_code)]
 impl ColorConfig {
-    fn file_string_to_color_config() {
-       
+    pub fn named(name: &str) -> Option<ColorConfig> {
+        match name {
+            "default" => Some(ColorConfig::default()),
+            "light" => Some(ColorConfig {
+                dir_name_color: Color::DarkBlue,
+                forward_slash_color: Color::DarkRed,
+                link_slash_color: Color::DarkMagenta,
+                file_name_color: Color::Reset,
+                background_color: Color::Reset,
+            }),
+            "mono" | "none" => Some(ColorConfig::mono()),
+            _ => None
+        }
+    }
+
+    pub fn mono() -> ColorConfig {
+        ColorConfig {
+            dir_name_color: Color::Reset,
+            forward_slash_color: Color::Reset,
+            link_slash_color: Color::Reset,
+            file_name_color: Color::Reset,
+            background_color: Color::Reset,
+        }
+    }
+
+    /// Loads `theme`, which is either the name of a built-in theme, a theme
+    /// file, or the name of a file in `$XDG_CONFIG_HOME/fap/themes`.
+    pub fn load(theme: &str) -> io::Result<ColorConfig> {
+        if let Some(config) = ColorConfig::named(theme) {
+            return Ok(config);
+        }
+
+        let mut path = Path::new(theme).to_path_buf();
+        if !path.exists() {
+            if let Some(dir) = crate::config::config_dir() {
+                path = dir.join("themes").join(theme).with_extension("toml");
+            }
+        }
+        let text = std::fs::read_to_string(&path).map_err(|e| io::Error::new(
+            e.kind(),
+            format!("unknown theme {} (expected one of {}, or a theme file)", theme, THEMES.join(", "))
+        ))?;
+
+        ColorConfig::file_string_to_color_config(&text)
     }
-}
\ No newline at end of file
+
+    /// Parses a theme file: a TOML table whose keys are the fields of
+    /// `ColorConfig`. Missing keys keep the default theme's color.
+    pub fn file_string_to_color_config(text: &str) -> io::Result<ColorConfig> {
+        let table: toml::Table = text.parse().map_err(|e: toml::de::Error| {
+            io::Error::new(io::ErrorKind::InvalidData, e.message().to_string())
+        })?;
+
+        let mut config = ColorConfig::default();
+        for (key, value) in table {
+            let field = match key.as_str() {
+                "dir_name_color" => &mut config.dir_name_color,
+                "forward_slash_color" => &mut config.forward_slash_color,
+                "link_slash_color" => &mut config.link_slash_color,
+                "file_name_color" => &mut config.file_name_color,
+                "background_color" => &mut config.background_color,
+                _ => return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown theme key: {}", key)
+                ))
+            };
+            *field = match &value {
+                toml::Value::String(name) => parse_color(name),
+                toml::Value::Integer(n) => u8::try_from(*n).ok().map(Color::AnsiValue),
+                _ => None
+            }.ok_or(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid color for {}: {}", key, value)
+            ))?;
+        }
+
+        Ok(config)
+    }
+
+    /// Wraps `text` in the escape codes for `color` on the theme's background.
+    pub fn paint(&self, text: &str, color: Color) -> String {
+        let mut style = ContentStyle::new();
+        if color != Color::Reset {
+            style.foreground_color = Some(color);
+        }
+        if self.background_color != Color::Reset {
+            style.background_color = Some(self.background_color);
+        }
+        style.apply(text).to_string()
+    }
+}
+
+impl Default for ColorConfig {
+    fn default() -> Self {
+        Self {
+            dir_name_color: Color::Cyan,
+            forward_slash_color: Color::Red,
+            link_slash_color: Color::Rgb {r: 255, g: 192, b: 203},
+            file_name_color: Color::Reset,
+            background_color: Color::Reset,
+        }
+    }
+}
+
+/// Accepts crossterm's color names (`dark_red`), `reset` and `#rrggbb`.
+fn parse_color(name: &str) -> Option<Color> {
+    if name == "reset" || name == "default" {
+        return Some(Color::Reset);
+    }
+    if let Some(hex) = name.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        return Some(Color::Rgb { r: channel(0)?, g: channel(2)?, b: channel(4)? });
+    }
+    Color::try_from(name).ok()
+}
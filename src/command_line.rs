@@ -0,0 +1,281 @@
+use std::io::{self, Write};
+
+use crossterm::{
+    cursor::MoveTo,
+    event::{read, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    style::{Print, Stylize},
+    terminal::{self, Clear, ClearType},
+    queue
+};
+
+/// Single line editor drawn in the last row of the bottom reserved area.
+/// Keeps the history of every line that was submitted through it.
+#[derive(Default)]
+pub struct CommandLine {
+    history: Vec<String>,
+}
+
+/// State of an in-progress tab completion: the char index where the
+/// completed word starts, the candidates, and which one is inserted.
+struct Completion {
+    start: usize,
+    candidates: Vec<String>,
+    selected: usize,
+}
+
+impl CommandLine {
+    /// Reads a line of input after `prompt`. Returns `None` if the user
+    /// cancelled with Esc (or by deleting past the start of the line).
+    ///
+    /// `complete` receives the text before the cursor and returns the char
+    /// index where the word being completed starts, alongside the candidates
+    /// that should replace it.
+    pub fn read_line(
+        &mut self,
+        prompt: &str,
+        complete: &dyn Fn(&str) -> (usize, Vec<String>)
+    ) -> io::Result<Option<String>> {
+        self.edit(prompt, "", complete, &mut |_| Ok(()))
+    }
+
+    /// Same as `read_line`, starting from `initial` and without completion.
+    /// `changed` is called with the line every time it changes, e.g. to
+    /// show what it does while it is being typed.
+    pub fn read_line_live(
+        &mut self,
+        prompt: &str,
+        initial: &str,
+        changed: &mut dyn FnMut(&str) -> io::Result<()>
+    ) -> io::Result<Option<String>> {
+        self.edit(prompt, initial, &|_| (0, vec![]), changed)
+    }
+
+    fn edit(
+        &mut self,
+        prompt: &str,
+        initial: &str,
+        complete: &dyn Fn(&str) -> (usize, Vec<String>),
+        changed: &mut dyn FnMut(&str) -> io::Result<()>
+    ) -> io::Result<Option<String>> {
+        let mut line: Vec<char> = initial.chars().collect();
+        let mut cursor = line.len();
+        let mut last = initial.to_string();
+        let mut history_index: Option<usize> = None;
+        let mut history_prefix = String::new();
+        let mut completion: Option<Completion> = None;
+
+        loop {
+            let current: String = line.iter().collect();
+            if current != last {
+                changed(&current)?;
+                last = current;
+            }
+            self.render(prompt, &line, cursor, completion.as_ref())?;
+
+            let key = match read()? {
+                Event::Key(key) if key.kind != KeyEventKind::Release => key,
+                _ => continue
+            };
+
+            if !matches!(key.code, KeyCode::Tab | KeyCode::BackTab) {
+                completion = None;
+            }
+            if !matches!(key.code, KeyCode::Up | KeyCode::Down) {
+                history_index = None;
+            }
+
+            match key {
+                KeyEvent{code: KeyCode::Enter, ..} => {
+                    let line: String = line.into_iter().collect();
+                    self.push_history(&line);
+                    return Ok(Some(line));
+                },
+                KeyEvent{code: KeyCode::Esc, ..}
+                | KeyEvent{code: KeyCode::Char('c'), modifiers: KeyModifiers::CONTROL, ..} => {
+                    return Ok(None);
+                },
+                KeyEvent{code: KeyCode::Backspace, ..} => {
+                    if line.is_empty() {
+                        return Ok(None);
+                    }
+                    if cursor > 0 {
+                        cursor -= 1;
+                        line.remove(cursor);
+                    }
+                },
+                KeyEvent{code: KeyCode::Delete, ..} if cursor < line.len() => {
+                    line.remove(cursor);
+                },
+                KeyEvent{code: KeyCode::Left, ..}
+                | KeyEvent{code: KeyCode::Char('b'), modifiers: KeyModifiers::CONTROL, ..} => {
+                    cursor = cursor.saturating_sub(1);
+                },
+                KeyEvent{code: KeyCode::Right, ..}
+                | KeyEvent{code: KeyCode::Char('f'), modifiers: KeyModifiers::CONTROL, ..} => {
+                    cursor = (cursor + 1).min(line.len());
+                },
+                KeyEvent{code: KeyCode::Home, ..}
+                | KeyEvent{code: KeyCode::Char('a'), modifiers: KeyModifiers::CONTROL, ..} => {
+                    cursor = 0;
+                },
+                KeyEvent{code: KeyCode::End, ..}
+                | KeyEvent{code: KeyCode::Char('e'), modifiers: KeyModifiers::CONTROL, ..} => {
+                    cursor = line.len();
+                },
+                KeyEvent{code: KeyCode::Char('w'), modifiers: KeyModifiers::CONTROL, ..} => {
+                    let mut start = cursor;
+                    while start > 0 && line[start - 1] == ' ' {
+                        start -= 1;
+                    }
+                    while start > 0 && line[start - 1] != ' ' {
+                        start -= 1;
+                    }
+                    line.drain(start..cursor);
+                    cursor = start;
+                },
+                KeyEvent{code: KeyCode::Char('u'), modifiers: KeyModifiers::CONTROL, ..} => {
+                    line.drain(..cursor);
+                    cursor = 0;
+                },
+                KeyEvent{code: KeyCode::Up, ..} | KeyEvent{code: KeyCode::Down, ..} => {
+                    if history_index.is_none() {
+                        history_prefix = line.iter().collect();
+                    }
+                    let found = if key.code == KeyCode::Up {
+                        self.history_before(history_index, &history_prefix)
+                    } else {
+                        self.history_after(history_index, &history_prefix)
+                    };
+                    history_index = found;
+                    let entry = match found {
+                        Some(i) => self.history[i].clone(),
+                        None if key.code == KeyCode::Down => history_prefix.clone(),
+                        None => continue
+                    };
+                    line = entry.chars().collect();
+                    cursor = line.len();
+                },
+                KeyEvent{code: KeyCode::Tab, ..} | KeyEvent{code: KeyCode::BackTab, ..} => {
+                    let forward = key.code == KeyCode::Tab;
+                    let state = match completion.take() {
+                        Some(mut state) => {
+                            let len = state.candidates.len();
+                            state.selected = if forward {
+                                (state.selected + 1) % len
+                            } else {
+                                (state.selected + len - 1) % len
+                            };
+                            state
+                        },
+                        None => {
+                            let before: String = line[..cursor].iter().collect();
+                            let (start, candidates) = complete(&before);
+                            if candidates.is_empty() {
+                                continue;
+                            }
+                            let start = start.min(cursor);
+                            // Extend to the longest common prefix first, like a shell.
+                            let common = common_prefix(&candidates);
+                            if candidates.len() > 1 && common.chars().count() > cursor - start {
+                                line.splice(start..cursor, common.chars());
+                                cursor = start + common.chars().count();
+                                continue;
+                            }
+                            Completion { start, candidates, selected: 0 }
+                        }
+                    };
+
+                    // A repeated tab press replaces the previously inserted
+                    // candidate, which always ends at the cursor.
+                    let replacement = &state.candidates[state.selected];
+                    line.splice(state.start..cursor, replacement.chars());
+                    cursor = state.start + replacement.chars().count();
+                    if state.candidates.len() > 1 {
+                        completion = Some(state);
+                    }
+                },
+                KeyEvent{code: KeyCode::Char(c), modifiers, ..}
+                    if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+                {
+                    line.insert(cursor, c);
+                    cursor += 1;
+                },
+                _ => ()
+            }
+        }
+    }
+
+    pub fn push_history(&mut self, line: &str) {
+        if line.trim().is_empty() {
+            return;
+        }
+        self.history.retain(|entry| entry != line);
+        self.history.push(line.to_string());
+    }
+
+    fn history_before(&self, index: Option<usize>, prefix: &str) -> Option<usize> {
+        let end = index.unwrap_or(self.history.len());
+        (0..end).rev()
+            .find(|&i| self.history[i].starts_with(prefix))
+            .or(index)
+    }
+
+    fn history_after(&self, index: Option<usize>, prefix: &str) -> Option<usize> {
+        let start = index? + 1;
+        (start..self.history.len())
+            .find(|&i| self.history[i].starts_with(prefix))
+    }
+
+    fn render(
+        &self,
+        prompt: &str,
+        line: &[char],
+        cursor: usize,
+        completion: Option<&Completion>
+    ) -> io::Result<()> {
+        let size = terminal::window_size()?;
+        let (cols, rows) = (size.columns as usize, size.rows);
+        let mut out = io::stderr().lock();
+
+        if let Some(completion) = completion {
+            queue!(out, MoveTo(0, rows.saturating_sub(2)), Clear(ClearType::CurrentLine))?;
+            let mut width = 0;
+            for (i, candidate) in completion.candidates.iter().enumerate() {
+                width += candidate.chars().count() + 2;
+                if width > cols {
+                    break;
+                }
+                if i == completion.selected {
+                    queue!(out, Print(candidate.as_str().reverse()), Print("  "))?;
+                } else {
+                    queue!(out, Print(candidate), Print("  "))?;
+                }
+            }
+        }
+
+        let prompt_len = prompt.chars().count();
+        let skip = (prompt_len + cursor + 1).saturating_sub(cols);
+        let text: String = prompt.chars()
+            .chain(line.iter().copied())
+            .skip(skip)
+            .take(cols)
+            .collect();
+
+        let row = rows.saturating_sub(1);
+        queue!(out, MoveTo(0, row), Clear(ClearType::CurrentLine), Print(text))?;
+        queue!(out, MoveTo((prompt_len + cursor - skip) as u16, row))?;
+        out.flush()
+    }
+}
+
+fn common_prefix(candidates: &[String]) -> String {
+    let mut prefix: Vec<char> = candidates[0].chars().collect();
+    for candidate in &candidates[1..] {
+        let len = prefix.iter()
+            .zip(candidate.chars())
+            .take_while(|(a, b)| *a == b)
+            .count();
+        prefix.truncate(len);
+    }
+    prefix.into_iter().collect()
+}
@@ -0,0 +1,714 @@
+use std::{io, path::{Path, PathBuf}};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::{app::App, settings::{ExecPolicy, FilterMode, SortKey, OPTIONS}, shell_init::posix_quote};
+
+/// An ex command that can be typed at the `:` prompt. Every command declares
+/// its arguments so that they can be validated and completed generically.
+pub struct Command {
+    pub name: &'static str,
+    pub args: &'static [Arg],
+    pub run: fn(&mut App, Args) -> io::Result<()>,
+}
+
+pub struct Arg {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub arity: Arity,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ArgKind {
+    /// Any path, relative paths are resolved from `App::cd`.
+    Path,
+    /// Same as `Path`, but only directories are completed.
+    Dir,
+    /// An option understood by `:set`.
+    Option,
+    SortKey,
+    /// A single key, as used by `:map`.
+    Key,
+    /// The rest of the line, taken verbatim.
+    Text,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Arity {
+    One,
+    Optional,
+    OneOrMore,
+    ZeroOrMore,
+}
+
+/// Arguments passed to a command after validation.
+pub struct Args {
+    /// Whether the command name was followed by `!`.
+    pub bang: bool,
+    pub values: Vec<String>,
+}
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "archive",
+        args: &[Arg { name: "name", kind: ArgKind::Path, arity: Arity::One }],
+        run: archive,
+    },
+    Command {
+        name: "cd",
+        args: &[Arg { name: "dir", kind: ArgKind::Dir, arity: Arity::Optional }],
+        run: cd,
+    },
+    Command {
+        name: "copy",
+        args: &[Arg { name: "dest", kind: ArgKind::Dir, arity: Arity::Optional }],
+        run: copy,
+    },
+    Command {
+        name: "extract",
+        args: &[Arg { name: "dest", kind: ArgKind::Dir, arity: Arity::Optional }],
+        run: extract,
+    },
+    Command {
+        name: "filter",
+        args: &[Arg { name: "pattern", kind: ArgKind::Text, arity: Arity::Optional }],
+        run: filter,
+    },
+    Command {
+        name: "flatten",
+        args: &[Arg { name: "depth", kind: ArgKind::Text, arity: Arity::Optional }],
+        run: flatten,
+    },
+    Command {
+        name: "map",
+        args: &[
+            Arg { name: "key", kind: ArgKind::Key, arity: Arity::Optional },
+            Arg { name: "keys", kind: ArgKind::Text, arity: Arity::Optional },
+        ],
+        run: map,
+    },
+    Command {
+        name: "mkdir",
+        args: &[Arg { name: "path", kind: ArgKind::Path, arity: Arity::OneOrMore }],
+        run: mkdir,
+    },
+    Command {
+        name: "move",
+        args: &[Arg { name: "dest", kind: ArgKind::Dir, arity: Arity::Optional }],
+        run: move_,
+    },
+    Command {
+        name: "only",
+        args: &[],
+        run: only,
+    },
+    Command {
+        name: "q",
+        args: &[],
+        run: quit,
+    },
+    Command {
+        name: "set",
+        args: &[Arg { name: "option", kind: ArgKind::Option, arity: Arity::ZeroOrMore }],
+        run: set,
+    },
+    Command {
+        name: "sort",
+        args: &[Arg { name: "key", kind: ArgKind::SortKey, arity: Arity::Optional }],
+        run: sort,
+    },
+    Command {
+        name: "tabclose",
+        args: &[],
+        run: tab_close,
+    },
+    Command {
+        name: "tabnew",
+        args: &[Arg { name: "dir", kind: ArgKind::Dir, arity: Arity::Optional }],
+        run: tab_new,
+    },
+    Command {
+        name: "unmap",
+        args: &[Arg { name: "key", kind: ArgKind::Key, arity: Arity::One }],
+        run: unmap,
+    },
+    Command {
+        name: "vsplit",
+        args: &[Arg { name: "dir", kind: ArgKind::Dir, arity: Arity::Optional }],
+        run: vsplit,
+    },
+];
+
+/// Parses and runs a line typed at the `:` prompt.
+pub fn execute(app: &mut App, line: &str) -> io::Result<()> {
+    let line = line.trim_start_matches([':', ' ']).trim_end();
+    if line.is_empty() {
+        return Ok(());
+    }
+
+    if let Ok(number) = line.parse::<usize>() {
+        return app.jump_to_line(number.saturating_sub(1));
+    }
+    if let Some(command) = line.strip_prefix('!') {
+        return app.run_shell(command);
+    }
+
+    let (name, bang, rest) = split_command(line);
+    let command = find_command(name)?;
+    let values = parse_args(command, rest)?;
+
+    (command.run)(app, Args { bang, values })
+}
+
+/// Returns the char index of the word being completed in `before`, and the
+/// candidates that can replace it.
+pub fn complete(app: &App, before: &str) -> (usize, Vec<String>) {
+    if let Some(command) = before.strip_prefix('!') {
+        let (start, candidates) = complete_args(app, command);
+        return (start + 1, candidates);
+    }
+
+    let word_start = last_word_start(before);
+    let word = unescape(&before[word_start..]);
+    let start = before[..word_start].chars().count();
+
+    let (name, _, rest) = split_command(before);
+    let rest_start = before.len() - rest.len();
+    if word_start < rest_start || rest_start == before.len() && !before.ends_with([' ', '!']) {
+        let names = COMMANDS.iter()
+            .map(|command| command.name.to_string())
+            .filter(|command| command.starts_with(&word))
+            .collect();
+        return (start, names);
+    }
+
+    let command = match find_command(name) {
+        Ok(command) => command,
+        Err(_) => return (start, vec![])
+    };
+
+    let mut position = 0;
+    let mut completed = &before[rest_start..word_start];
+    while let Some((_, next)) = next_arg(completed) {
+        position += 1;
+        completed = next;
+    }
+
+    let arg = match command.args.get(position).or_else(|| {
+        command.args.last().filter(|arg|
+            matches!(arg.arity, Arity::OneOrMore | Arity::ZeroOrMore)
+        )
+    }) {
+        Some(arg) => arg,
+        None => return (start, vec![])
+    };
+
+    let candidates = match arg.kind {
+        ArgKind::Path => complete_path(app, &word, false).iter().map(|path| escape(path)).collect(),
+        ArgKind::Dir => complete_path(app, &word, true).iter().map(|path| escape(path)).collect(),
+        ArgKind::Option => {
+            if let Some(key) = word.strip_prefix("sort=") {
+                SortKey::ALL.iter()
+                    .filter(|candidate| candidate.starts_with(key))
+                    .map(|candidate| format!("sort={}", candidate))
+                    .collect()
+            } else if let Some(policy) = word.strip_prefix("exec=") {
+                ExecPolicy::ALL.iter()
+                    .filter(|candidate| candidate.starts_with(policy))
+                    .map(|candidate| format!("exec={}", candidate))
+                    .collect()
+            } else if let Some(mode) = word.strip_prefix("filtermode=") {
+                FilterMode::ALL.iter()
+                    .filter(|candidate| candidate.starts_with(mode))
+                    .map(|candidate| format!("filtermode={}", candidate))
+                    .collect()
+            } else {
+                let negated = OPTIONS.iter()
+                    .filter(|option| app.settings.is_flag(option))
+                    .map(|option| format!("no{}", option));
+                OPTIONS.iter()
+                    .map(|option| option.to_string())
+                    .chain(negated)
+                    .filter(|option| option.starts_with(&word))
+                    .collect()
+            }
+        },
+        ArgKind::SortKey => SortKey::ALL.iter()
+            .filter(|key| key.starts_with(&word))
+            .map(|key| key.to_string())
+            .collect(),
+        ArgKind::Key | ArgKind::Text => vec![]
+    };
+
+    (start, candidates)
+}
+
+/// Completes the paths in a line of arguments for a program, which are
+/// handed to the shell as typed.
+pub fn complete_args(app: &App, before: &str) -> (usize, Vec<String>) {
+    let word_start = last_word_start(before);
+    let word = unescape(&before[word_start..]);
+    let start = before[..word_start].chars().count();
+
+    (start, complete_path(app, &word, false).iter().map(|path| escape(path)).collect())
+}
+
+/// Completes a line that is a single path, taken as typed.
+pub fn complete_line_path(app: &App, before: &str) -> (usize, Vec<String>) {
+    (0, complete_path(app, before, false))
+}
+
+/// Replaces the placeholders of a shell command: `%f` with `current`, `%s`
+/// with `targets` and `%d` with `cd`, all shell quoted. `%%` stands for `%`.
+pub fn expand_placeholders(command: &str, current: Option<&Path>, targets: &[PathBuf], cd: &Path) -> String {
+    let quote = |path: &Path| posix_quote(&path.to_string_lossy());
+
+    let mut expanded = String::new();
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('f') => expanded += &current.map(quote).unwrap_or_default(),
+            Some('s') => expanded += &targets.iter().map(|path| quote(path)).collect::<Vec<_>>().join(" "),
+            Some('d') => expanded += &quote(cd),
+            Some('%') => expanded.push('%'),
+            Some(c) => {
+                expanded.push('%');
+                expanded.push(c);
+            },
+            None => expanded.push('%')
+        }
+    }
+    expanded
+}
+
+/// Parses vim style key notation such as `5j`, `<CR>` or `<C-d>`.
+pub fn parse_keys(keys: &str) -> Vec<KeyEvent> {
+    let mut output = vec![];
+    let mut rest = keys;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some(end) = rest.find('>') {
+                let name = &rest[1..end];
+                if let Some(key) = parse_key_name(name) {
+                    output.push(key);
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+            }
+        }
+        output.push(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        rest = &rest[c.len_utf8()..];
+    }
+
+    output
+}
+
+fn parse_key_name(name: &str) -> Option<KeyEvent> {
+    if let Some(c) = name.strip_prefix("C-").or_else(|| name.strip_prefix("c-")) {
+        let mut chars = c.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(KeyEvent::new(
+                KeyCode::Char(c.to_ascii_lowercase()),
+                KeyModifiers::CONTROL
+            )),
+            _ => None
+        };
+    }
+
+    let code = match name.to_ascii_lowercase().as_str() {
+        "cr" | "enter" | "return" => KeyCode::Enter,
+        "esc" => KeyCode::Esc,
+        "space" => KeyCode::Char(' '),
+        "tab" => KeyCode::Tab,
+        "bs" => KeyCode::Backspace,
+        "lt" => KeyCode::Char('<'),
+        _ => return None
+    };
+
+    Some(KeyEvent::new(code, KeyModifiers::NONE))
+}
+
+fn split_command(line: &str) -> (&str, bool, &str) {
+    let end = line.find(|c: char| !c.is_alphanumeric()).unwrap_or(line.len());
+    let (name, rest) = line.split_at(end);
+    match rest.strip_prefix('!') {
+        Some(rest) => (name, true, rest.trim_start()),
+        None => (name, false, rest.trim_start())
+    }
+}
+
+/// Looks a command up by its full name, or by an unambiguous prefix.
+fn find_command(name: &str) -> io::Result<&'static Command> {
+    if let Some(command) = COMMANDS.iter().find(|command| command.name == name) {
+        return Ok(command);
+    }
+
+    let mut matches = COMMANDS.iter().filter(|command| command.name.starts_with(name));
+    match (matches.next(), matches.next()) {
+        (Some(command), None) if !name.is_empty() => Ok(command),
+        (Some(_), Some(_)) => Err(invalid(format!("ambiguous command: {}", name))),
+        _ => Err(invalid(format!("not a command: {}", name)))
+    }
+}
+
+fn parse_args(command: &Command, mut rest: &str) -> io::Result<Vec<String>> {
+    let mut values = vec![];
+
+    for arg in command.args {
+        let count = values.len();
+        if arg.kind == ArgKind::Text {
+            if !rest.is_empty() {
+                values.push(rest.to_string());
+                rest = "";
+            }
+        } else {
+            while let Some((value, next)) = next_arg(rest) {
+                values.push(value);
+                rest = next;
+                if matches!(arg.arity, Arity::One | Arity::Optional) {
+                    break;
+                }
+            }
+        }
+
+        if values.len() == count && matches!(arg.arity, Arity::One | Arity::OneOrMore) {
+            return Err(invalid(format!("{}: missing argument <{}>", command.name, arg.name)));
+        }
+    }
+
+    if !rest.is_empty() {
+        return Err(invalid(format!("{}: too many arguments", command.name)));
+    }
+
+    Ok(values)
+}
+
+/// Splits the next whitespace separated argument off `line`. Quotes and
+/// backslashes can be used to include spaces in an argument.
+fn next_arg(line: &str) -> Option<(String, &str)> {
+    let line = line.trim_start();
+    if line.is_empty() {
+        return None;
+    }
+
+    let mut value = String::new();
+    let mut quote = None;
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (c, quote) {
+            ('\\', _) => {
+                if let Some((_, c)) = chars.next() {
+                    value.push(c);
+                }
+            },
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            (' ', None) => return Some((value, line[i..].trim_start())),
+            (c, _) => value.push(c)
+        }
+    }
+
+    Some((value, ""))
+}
+
+fn last_word_start(line: &str) -> usize {
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '\\' if !escaped => {
+                escaped = true;
+                continue;
+            },
+            ' ' if !escaped => start = i + 1,
+            _ => ()
+        }
+        escaped = false;
+    }
+    start
+}
+
+fn unescape(word: &str) -> String {
+    next_arg(word).map(|(value, _)| value).unwrap_or_default()
+}
+
+fn escape(word: &str) -> String {
+    word.replace('\\', "\\\\").replace(' ', "\\ ")
+}
+
+/// Resolves a path typed by the user: `~` expands to the home directory and
+/// relative paths are taken from `cd`.
+pub fn resolve_path(cd: &Path, path: &str) -> PathBuf {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let path = match (path.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            home.join(rest.trim_start_matches('/'))
+        },
+        _ => PathBuf::from(path)
+    };
+    cd.join(path)
+}
+
+fn complete_path(app: &App, word: &str, dirs_only: bool) -> Vec<String> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(i) => (&word[..=i], &word[i + 1..]),
+        None => ("", word)
+    };
+    let listing = match app.vfs.list(&resolve_path(&app.cd, if dir.is_empty() { "." } else { dir })) {
+        Ok(listing) => listing,
+        Err(_) => return vec![]
+    };
+
+    let mut candidates: Vec<String> = listing
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.path.file_name()?.to_str()?.to_string();
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let is_dir = entry.is_dir(app.vfs.as_ref());
+            if dirs_only && !is_dir {
+                return None;
+            }
+            let suffix = if is_dir { "/" } else { "" };
+            Some(format!("{}{}{}", dir, name, suffix))
+        })
+        .collect();
+    candidates.sort();
+
+    candidates
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn cd(app: &mut App, args: Args) -> io::Result<()> {
+    let path = match args.values.first() {
+        Some(path) => resolve_path(&app.cd, path),
+        None => resolve_path(&app.cd, "~")
+    };
+    app.change_dir(path)
+}
+
+fn copy(app: &mut App, args: Args) -> io::Result<()> {
+    let dest = transfer_dest(app, &args)?;
+    app.transfer(&dest, true)
+}
+
+fn move_(app: &mut App, args: Args) -> io::Result<()> {
+    let dest = transfer_dest(app, &args)?;
+    app.transfer(&dest, false)
+}
+
+/// Copy and move go to the other pane unless told otherwise.
+fn transfer_dest(app: &App, args: &Args) -> io::Result<PathBuf> {
+    match (args.values.first(), app.other_pane_dir()) {
+        (Some(path), _) => Ok(resolve_path(&app.cd, path)),
+        (None, Some(dir)) => Ok(dir.clone()),
+        (None, None) => Err(invalid("no destination: give one or open a second pane with :vsplit".to_string()))
+    }
+}
+
+fn filter(app: &mut App, args: Args) -> io::Result<()> {
+    app.set_filter(args.values.into_iter().next());
+    app.resort()
+}
+
+/// Without a depth, toggles between a flat listing of the whole tree and
+/// the usual one. A depth of 0 goes back to the usual listing.
+fn flatten(app: &mut App, args: Args) -> io::Result<()> {
+    let depth = match args.values.first() {
+        Some(depth) => match depth.parse::<usize>() {
+            Ok(0) => None,
+            Ok(depth) => Some(depth),
+            Err(_) => return Err(invalid(format!("flatten: <depth> must be a number: {}", depth)))
+        },
+        None if app.flat.is_some() => None,
+        None => Some(usize::MAX)
+    };
+    app.flatten(depth)
+}
+
+fn map(app: &mut App, args: Args) -> io::Result<()> {
+    let mut values = args.values.into_iter();
+    let key = match values.next() {
+        Some(key) => key,
+        None => {
+            let mut mappings: Vec<String> = app.mappings.iter()
+                .map(|(key, keys)| format!("{} {}", key, keys))
+                .collect();
+            mappings.sort();
+            app.status = if mappings.is_empty() {
+                "no mappings".to_string()
+            } else {
+                mappings.join(" | ")
+            };
+            return Ok(());
+        }
+    };
+
+    let mut chars = key.chars();
+    let key = match (chars.next(), chars.next()) {
+        (Some(c), None) => c,
+        _ => return Err(invalid(format!("map: <key> must be a single character: {}", key)))
+    };
+
+    match values.next() {
+        Some(keys) => {
+            app.mappings.insert(key, keys);
+        },
+        None => {
+            app.status = match app.mappings.get(&key) {
+                Some(keys) => format!("{} {}", key, keys),
+                None => format!("no mapping for {}", key)
+            };
+        }
+    }
+
+    Ok(())
+}
+
+fn unmap(app: &mut App, args: Args) -> io::Result<()> {
+    let key = &args.values[0];
+    let removed = key.chars().next()
+        .filter(|_| key.chars().count() == 1)
+        .and_then(|key| app.mappings.remove(&key));
+    if removed.is_none() {
+        return Err(invalid(format!("no mapping for {}", key)));
+    }
+    Ok(())
+}
+
+fn archive(app: &mut App, args: Args) -> io::Result<()> {
+    app.compress(&args.values[0])
+}
+
+fn extract(app: &mut App, args: Args) -> io::Result<()> {
+    app.extract(args.values.first().map(String::as_str))
+}
+
+fn mkdir(app: &mut App, args: Args) -> io::Result<()> {
+    for path in &args.values {
+        app.vfs.create_dir(&resolve_path(&app.cd, path))?;
+    }
+    app.refresh()
+}
+
+fn only(app: &mut App, _args: Args) -> io::Result<()> {
+    app.close_pane()
+}
+
+fn quit(app: &mut App, _args: Args) -> io::Result<()> {
+    if app.tabs.len() > 1 {
+        return app.close_tab();
+    }
+    app.cancel();
+    Ok(())
+}
+
+fn set(app: &mut App, args: Args) -> io::Result<()> {
+    if args.values.is_empty() {
+        app.status = app.settings.to_string();
+        return Ok(());
+    }
+
+    for option in &args.values {
+        if let Some(name) = option.strip_suffix('?') {
+            app.status = app.settings.get(name)?;
+        } else {
+            app.settings.set(option)?;
+        }
+    }
+
+    app.resort()
+}
+
+fn sort(app: &mut App, args: Args) -> io::Result<()> {
+    if let Some(key) = args.values.first() {
+        app.settings.sort = SortKey::parse(key)?;
+    }
+    app.settings.reverse = args.bang;
+    app.resort()
+}
+
+fn tab_close(app: &mut App, _args: Args) -> io::Result<()> {
+    app.close_tab()
+}
+
+fn tab_new(app: &mut App, args: Args) -> io::Result<()> {
+    let path = match args.values.first() {
+        Some(path) => resolve_path(&app.cd, path),
+        None => app.cd.clone()
+    };
+    app.new_tab(path)
+}
+
+fn vsplit(app: &mut App, args: Args) -> io::Result<()> {
+    let path = match args.values.first() {
+        Some(path) => resolve_path(&app.cd, path),
+        None => app.cd.clone()
+    };
+    app.open_pane(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(name: &str, rest: &str) -> io::Result<Vec<String>> {
+        parse_args(find_command(name)?, rest)
+    }
+
+    #[test]
+    fn splits_args_on_spaces_quotes_and_escapes() {
+        assert_eq!(next_arg("  a b"), Some(("a".to_string(), "b")));
+        assert_eq!(next_arg(r#""a b" c"#), Some(("a b".to_string(), "c")));
+        assert_eq!(next_arg("'say \"hi\"'"), Some(("say \"hi\"".to_string(), "")));
+        assert_eq!(next_arg(r"a\ b\\c d"), Some((r"a b\c".to_string(), "d")));
+        assert_eq!(next_arg("x'y z'w"), Some(("xy zw".to_string(), "")));
+        assert_eq!(next_arg("   "), None);
+    }
+
+    #[test]
+    fn parses_args_by_arity() {
+        assert_eq!(args("mkdir", r#"a "b c" d\ e"#).unwrap(), ["a", "b c", "d e"]);
+        assert_eq!(args("cd", "").unwrap(), Vec::<String>::new());
+        assert_eq!(args("filter", "*.rs  x").unwrap(), ["*.rs  x"]);
+        assert_eq!(args("map", "J 5j").unwrap(), ["J", "5j"]);
+        assert!(args("mkdir", "").is_err());
+        assert!(args("cd", "a b").is_err());
+        assert!(args("only", "x").is_err());
+    }
+
+    #[test]
+    fn finds_commands_by_unambiguous_prefix() {
+        assert_eq!(find_command("vs").unwrap().name, "vsplit");
+        assert_eq!(find_command("tabn").unwrap().name, "tabnew");
+        assert_eq!(find_command("cd").unwrap().name, "cd");
+
+        let ambiguous = find_command("ta").map(|command| command.name).unwrap_err();
+        assert_eq!(ambiguous.to_string(), "ambiguous command: ta");
+        assert!(find_command("m").is_err());
+        assert!(find_command("nope").is_err());
+        assert!(find_command("").is_err());
+    }
+
+    #[test]
+    fn expands_placeholders_shell_quoted() {
+        let targets = [PathBuf::from("/home/a b"), PathBuf::from("/home/it's")];
+        let current = Some(Path::new("/home/a b"));
+        let cd = Path::new("/home");
+
+        assert_eq!(expand_placeholders("less %f", current, &targets, cd), "less '/home/a b'");
+        assert_eq!(expand_placeholders("rm %s", current, &targets, cd), r"rm '/home/a b' '/home/it'\''s'");
+        assert_eq!(expand_placeholders("cd %d && ls", current, &targets, cd), "cd '/home' && ls");
+        assert_eq!(expand_placeholders("echo 100%% %x %", current, &targets, cd), "echo 100% %x %");
+        assert_eq!(expand_placeholders("echo %f%s", None, &[], cd), "echo ");
+    }
+}
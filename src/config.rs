@@ -0,0 +1,70 @@
+use std::{io, path::{Path, PathBuf}};
+
+use crate::{app::App, color_config::ColorConfig};
+
+/// `$XDG_CONFIG_HOME/fap`, falling back to `~/.config/fap`.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(base.join("fap"))
+}
+
+/// Applies the config file at `path`, or `config.toml` in `config_dir` if it
+/// exists. Top level keys are `:set` options, besides `theme` and the
+/// `[mappings]` table:
+///
+/// ```toml
+/// hidden = true
+/// sort = "mtime"
+/// theme = "light"
+///
+/// [mappings]
+/// J = "5j"
+/// ```
+pub fn load(app: &mut App, path: Option<&Path>) -> io::Result<()> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => match config_dir().map(|dir| dir.join("config.toml")) {
+            Some(path) if path.exists() => path,
+            _ => return Ok(())
+        }
+    };
+
+    std::fs::read_to_string(&path)
+        .and_then(|text| apply(app, &text))
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+fn apply(app: &mut App, text: &str) -> io::Result<()> {
+    let table: toml::Table = text.parse().map_err(|e: toml::de::Error| {
+        io::Error::new(io::ErrorKind::InvalidData, e.message().to_string())
+    })?;
+
+    for (key, value) in table {
+        match (key.as_str(), value) {
+            ("theme", toml::Value::String(theme)) => app.theme = ColorConfig::load(&theme)?,
+            ("mappings", toml::Value::Table(mappings)) => {
+                for (key, keys) in mappings {
+                    let mut chars = key.chars();
+                    let key = match (chars.next(), chars.next(), keys) {
+                        (Some(c), None, toml::Value::String(keys)) => (c, keys),
+                        _ => return Err(invalid(format!("invalid mapping for {}", key)))
+                    };
+                    app.mappings.insert(key.0, key.1);
+                }
+            },
+            (_, toml::Value::String(value)) => app.settings.set(&format!("{}={}", key, value))?,
+            (_, toml::Value::Boolean(value)) => app.settings.set(&format!("{}={}", key, value))?,
+            (_, toml::Value::Integer(value)) => app.settings.set(&format!("{}={}", key, value))?,
+            (_, value) => return Err(invalid(format!("invalid value for {}: {}", key, value)))
+        }
+    }
+
+    Ok(())
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,296 @@
+use std::{io, path::{Path, PathBuf}};
+
+use is_executable::IsExecutable;
+
+/// The `[Desktop Entry]` group of a freedesktop `.desktop` file, as far as
+/// launching it goes.
+pub struct DesktopEntry {
+    /// `Type`: Application, Link or Directory.
+    pub kind: String,
+    /// `Name`, in the user's language when the file has a translation.
+    pub name: Option<String>,
+    pub exec: Option<String>,
+    pub try_exec: Option<String>,
+    /// Directory to run the program in.
+    pub path: Option<PathBuf>,
+    pub terminal: bool,
+    pub icon: Option<String>,
+    /// Target of a Link entry.
+    pub url: Option<String>,
+}
+
+impl DesktopEntry {
+    pub fn load(path: &Path) -> io::Result<DesktopEntry> {
+        DesktopEntry::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<DesktopEntry> {
+        let mut entry = DesktopEntry {
+            kind: String::new(),
+            name: None,
+            exec: None,
+            try_exec: None,
+            path: None,
+            terminal: false,
+            icon: None,
+            url: None,
+        };
+        let locales = locales();
+        // Index in `locales` of the translation of Name that was picked.
+        let mut name_rank = usize::MAX;
+
+        let mut in_group = false;
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                in_group = line == "[Desktop Entry]";
+                continue;
+            }
+            if !in_group {
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), unescape(value.trim())),
+                None => continue
+            };
+            if let Some(locale) = key.strip_prefix("Name[").and_then(|key| key.strip_suffix(']')) {
+                if let Some(rank) = locales.iter().position(|candidate| candidate == locale) {
+                    if rank < name_rank {
+                        entry.name = Some(value);
+                        name_rank = rank;
+                    }
+                }
+                continue;
+            }
+
+            match key {
+                "Type" => entry.kind = value,
+                "Name" if entry.name.is_none() => entry.name = Some(value),
+                "Exec" => entry.exec = Some(value),
+                "TryExec" => entry.try_exec = Some(value),
+                "Path" if !value.is_empty() => entry.path = Some(PathBuf::from(value)),
+                "Terminal" => entry.terminal = value == "true",
+                "Icon" => entry.icon = Some(value),
+                "URL" => entry.url = Some(value),
+                _ => ()
+            }
+        }
+
+        if entry.kind.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a desktop entry"));
+        }
+        Ok(entry)
+    }
+
+    /// Whether the program named by `TryExec` is installed. Entries without
+    /// `TryExec` always are.
+    pub fn is_installed(&self) -> bool {
+        let program = match &self.try_exec {
+            Some(program) => Path::new(program),
+            None => return true
+        };
+        if program.is_absolute() {
+            return program.is_executable();
+        }
+        std::env::var_os("PATH").is_some_and(|paths| {
+            std::env::split_paths(&paths).any(|dir| dir.join(program).is_executable())
+        })
+    }
+
+    /// Splits `Exec` into the program and its arguments, expanding the
+    /// field codes: `%f`/`%u` to the first of `files`, `%F`/`%U` to all of
+    /// them, `%i` to the icon, `%c` to the name and `%k` to `location`,
+    /// the path of the desktop file itself.
+    pub fn command(&self, location: &Path, files: &[PathBuf]) -> io::Result<Vec<String>> {
+        let exec = match &self.exec {
+            Some(exec) => exec,
+            None => return Err(invalid("no Exec key"))
+        };
+
+        let mut command = vec![];
+        for arg in split_exec(exec)? {
+            match arg.as_str() {
+                "%F" | "%U" => command.extend(files.iter().map(|file| file.display().to_string())),
+                "%i" => {
+                    if let Some(icon) = &self.icon {
+                        command.push("--icon".to_string());
+                        command.push(icon.clone());
+                    }
+                },
+                _ => {
+                    // A lone %f without files leaves no argument behind,
+                    // rather than an empty one.
+                    let expanded = expand_fields(&arg, self, location, files);
+                    if !expanded.is_empty() || !is_field_code(&arg) {
+                        command.push(expanded);
+                    }
+                }
+            }
+        }
+
+        if command.is_empty() {
+            return Err(invalid("empty Exec key"));
+        }
+        Ok(command)
+    }
+}
+
+fn is_field_code(arg: &str) -> bool {
+    arg.len() == 2 && arg.starts_with('%')
+}
+
+/// Expands the field codes that stand for a single value inside `arg`.
+/// Deprecated ones expand to nothing.
+fn expand_fields(arg: &str, entry: &DesktopEntry, location: &Path, files: &[PathBuf]) -> String {
+    let mut expanded = String::new();
+    let mut chars = arg.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => expanded.push('%'),
+            Some('f' | 'u' | 'F' | 'U') => {
+                if let Some(file) = files.first() {
+                    expanded += &file.display().to_string();
+                }
+            },
+            Some('c') => expanded += entry.name.as_deref().unwrap_or_default(),
+            Some('k') => expanded += &location.display().to_string(),
+            _ => ()
+        }
+    }
+    expanded
+}
+
+/// Splits an `Exec` value into arguments. Arguments may be double quoted,
+/// in which case `\"`, `` \` ``, `\$` and `\\` stand for the character.
+fn split_exec(exec: &str) -> io::Result<Vec<String>> {
+    let mut args = vec![];
+    let mut arg: Option<String> = None;
+    let mut chars = exec.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' => {
+                if let Some(arg) = arg.take() {
+                    args.push(arg);
+                }
+            },
+            '"' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped) => arg.push(escaped),
+                            None => return Err(invalid("unterminated quote in Exec"))
+                        },
+                        Some(c) => arg.push(c),
+                        None => return Err(invalid("unterminated quote in Exec"))
+                    }
+                }
+            },
+            c => arg.get_or_insert_with(String::new).push(c)
+        }
+    }
+    if let Some(arg) = arg {
+        args.push(arg);
+    }
+
+    Ok(args)
+}
+
+/// Undoes the escapes of desktop file values: `\s`, `\n`, `\t`, `\r` and `\\`.
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => unescaped.push(' '),
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some('\\') => unescaped.push('\\'),
+            Some(c) => {
+                unescaped.push('\\');
+                unescaped.push(c);
+            },
+            None => unescaped.push('\\')
+        }
+    }
+    unescaped
+}
+
+/// The locales whose translations are looked for, best match first, e.g.
+/// `sr_YU@Latn`, `sr_YU`, `sr@Latn`, `sr` for `LANG=sr_YU.UTF-8@Latn`.
+fn locales() -> Vec<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|locale| !locale.is_empty())
+        .unwrap_or_default();
+
+    let (locale, modifier) = match locale.split_once('@') {
+        Some((locale, modifier)) => (locale, Some(modifier)),
+        None => (locale.as_str(), None)
+    };
+    let locale = locale.split('.').next().unwrap_or_default();
+    let lang = locale.split('_').next().unwrap_or_default();
+    if lang.is_empty() || lang == "C" || lang == "POSIX" {
+        return vec![];
+    }
+
+    let mut locales = vec![];
+    for base in [locale, lang] {
+        if let Some(modifier) = modifier {
+            locales.push(format!("{}@{}", base, modifier));
+        }
+        locales.push(base.to_string());
+    }
+    locales.dedup();
+    locales
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(exec: &str, files: &[&str]) -> Vec<String> {
+        let entry = DesktopEntry::parse(&format!("[Desktop Entry]\nType=Application\nName=Edit\nExec={}", exec)).unwrap();
+        let files: Vec<PathBuf> = files.iter().map(PathBuf::from).collect();
+        entry.command(Path::new("/apps/edit.desktop"), &files).unwrap()
+    }
+
+    #[test]
+    fn splits_quoted_exec_arguments() {
+        assert_eq!(split_exec("edit  -n\t%f").unwrap(), ["edit", "-n", "%f"]);
+        assert_eq!(split_exec(r#""/opt/my app/edit" --title="a b""#).unwrap(), ["/opt/my app/edit", "--title=a b"]);
+        assert_eq!(split_exec(r#"sh -c "echo \"\$HOME\" \\ \`x\`""#).unwrap(), ["sh", "-c", r#"echo "$HOME" \ `x`"#]);
+        assert_eq!(split_exec(r#"edit """#).unwrap(), ["edit", ""]);
+        assert!(split_exec(r#"edit "a"#).is_err());
+    }
+
+    #[test]
+    fn expands_field_codes() {
+        assert_eq!(command("edit %f", &["/a b", "/c"]), ["edit", "/a b"]);
+        assert_eq!(command("edit %u", &["/a"]), ["edit", "/a"]);
+        assert_eq!(command("edit %F", &["/a b", "/c"]), ["edit", "/a b", "/c"]);
+        assert_eq!(command("edit %U --", &[]), ["edit", "--"]);
+        assert_eq!(command("edit %f", &[]), ["edit"]);
+        assert_eq!(command("edit --file=%f", &[]), ["edit", "--file="]);
+        assert_eq!(command("edit 100%% %c %k %d", &[]), ["edit", "100%", "Edit", "/apps/edit.desktop"]);
+    }
+}
@@ -0,0 +1,309 @@
+use std::{
+    collections::{HashMap, HashSet}, path::{Path, PathBuf},
+    sync::{atomic::{AtomicBool, Ordering}, mpsc::{self, Receiver, Sender}, Arc, Mutex},
+    thread, time::{Duration, Instant, SystemTime}
+};
+
+use crate::vfs::{Stat, Vfs};
+
+/// How often a directory that is still being measured reports its size so
+/// far.
+const REPORT_INTERVAL: Duration = Duration::from_millis(200);
+const MAX_WORKERS: usize = 8;
+
+/// Measures the disk usage of entries, whole directory trees included, on
+/// a pool of background threads. Results are cached by file id and
+/// modification time, so that going back to a directory does not measure
+/// it again unless it changed.
+pub struct DiskUsage {
+    /// Sizes of the entries asked for, possibly still being measured.
+    sizes: HashMap<PathBuf, Measured>,
+    /// Entries being measured, with the flag that cancels them.
+    pending: HashMap<PathBuf, Arc<AtomicBool>>,
+    cache: Arc<Mutex<HashMap<Key, Usage>>>,
+    /// Started along with the workers, the first time something is measured.
+    jobs: Option<Sender<Job>>,
+    reports: Receiver<Report>,
+    report_sender: Sender<Report>,
+}
+
+/// What an entry adds up to. Hard links to the same file are counted once
+/// in `apparent` and `disk`, and as often as they are found in the `_links`
+/// variants.
+#[derive(Clone, Copy, Default)]
+pub struct Usage {
+    pub apparent: u64,
+    /// Space taken by the blocks allocated on disk.
+    pub disk: u64,
+    pub apparent_links: u64,
+    pub disk_links: u64,
+}
+
+struct Measured {
+    usage: Usage,
+    /// Whether `usage` is final, rather than what was counted so far.
+    done: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Key {
+    id: (u64, u64),
+    modified: Option<SystemTime>,
+}
+
+struct Job {
+    vfs: Arc<dyn Vfs>,
+    path: PathBuf,
+    cancelled: Arc<AtomicBool>,
+}
+
+struct Report {
+    path: PathBuf,
+    usage: Usage,
+    done: bool,
+}
+
+impl Usage {
+    pub fn size(&self, apparent: bool, dedup: bool) -> u64 {
+        match (apparent, dedup) {
+            (true, true) => self.apparent,
+            (true, false) => self.apparent_links,
+            (false, true) => self.disk,
+            (false, false) => self.disk_links,
+        }
+    }
+
+    /// Counts the file or directory itself, not what is inside it. Files
+    /// with more than one link are counted once per `seen` set.
+    fn add(&mut self, stat: &Stat, seen: &mut HashSet<(u64, u64)>) {
+        let (apparent, disk) = (stat.len, stat.disk_len);
+        self.apparent_links += apparent;
+        self.disk_links += disk;
+        if !stat.is_dir && stat.links > 1 {
+            if let Some(id) = stat.id {
+                if !seen.insert(id) {
+                    return;
+                }
+            }
+        }
+        self.apparent += apparent;
+        self.disk += disk;
+    }
+}
+
+impl DiskUsage {
+    pub fn new() -> DiskUsage {
+        let (report_sender, reports) = mpsc::channel();
+        DiskUsage {
+            sizes: HashMap::new(),
+            pending: HashMap::new(),
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            jobs: None,
+            reports,
+            report_sender,
+        }
+    }
+
+    /// Starts measuring every path of `paths` on `vfs` whose size is not
+    /// known yet, and cancels whatever is being measured that is not in
+    /// `paths` anymore.
+    pub fn measure(&mut self, vfs: &Arc<dyn Vfs>, paths: &[PathBuf]) {
+        let wanted: HashSet<&PathBuf> = paths.iter().collect();
+        self.pending.retain(|path, cancelled| {
+            let keep = wanted.contains(path);
+            if !keep {
+                cancelled.store(true, Ordering::Relaxed);
+            }
+            keep
+        });
+        self.sizes.retain(|path, _| wanted.contains(path));
+
+        for path in paths {
+            if self.pending.contains_key(path) || self.sizes.get(path).is_some_and(|measured| measured.done) {
+                continue;
+            }
+            let cancelled = Arc::new(AtomicBool::new(false));
+            let job = Job { vfs: vfs.clone(), path: path.clone(), cancelled: cancelled.clone() };
+            if self.jobs().send(job).is_ok() {
+                self.pending.insert(path.clone(), cancelled);
+            }
+        }
+    }
+
+    /// Has the entries under `dir`, however deep, measured again the next
+    /// time they are asked for. They keep their last size until the new one
+    /// comes in, which for directories that did not change is right away,
+    /// from the cache.
+    pub fn refresh(&mut self, dir: &Path) {
+        for (path, measured) in &mut self.sizes {
+            if path.starts_with(dir) && path != dir {
+                measured.done = false;
+            }
+        }
+    }
+
+    /// Forgets every size measured so far, e.g. because files changed deep
+    /// down a directory, which the modification time of the directory
+    /// itself does not tell.
+    pub fn clear_cache(&mut self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    pub fn is_busy(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Takes the sizes reported since the last call. Returns whether any
+    /// size changed.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        for report in self.reports.try_iter() {
+            // Reports of cancelled jobs may still be in the channel.
+            if !self.pending.contains_key(&report.path) {
+                continue;
+            }
+            if report.done {
+                self.pending.remove(&report.path);
+            }
+            self.sizes.insert(report.path, Measured { usage: report.usage, done: report.done });
+            changed = true;
+        }
+        changed
+    }
+
+    /// The size of `path`, once at least part of it was measured.
+    pub fn size(&self, path: &Path, apparent: bool, dedup: bool) -> Option<u64> {
+        self.sizes.get(path).map(|measured| measured.usage.size(apparent, dedup))
+    }
+
+    /// The size of `path` as listed, with `…` while it is being measured.
+    pub fn label(&self, path: &Path, apparent: bool, dedup: bool) -> String {
+        let measured = match self.sizes.get(path) {
+            Some(measured) => measured,
+            None => return "…".to_string()
+        };
+        let size = human_size(measured.usage.size(apparent, dedup));
+        if measured.done {
+            return size;
+        }
+        format!("{}…", size)
+    }
+
+    fn jobs(&mut self) -> &Sender<Job> {
+        self.jobs.get_or_insert_with(|| {
+            let (sender, receiver) = mpsc::channel::<Job>();
+            let receiver = Arc::new(Mutex::new(receiver));
+            let workers = thread::available_parallelism().map_or(2, |n| n.get()).min(MAX_WORKERS);
+            for _ in 0..workers {
+                let receiver = receiver.clone();
+                let reports = self.report_sender.clone();
+                let cache = self.cache.clone();
+                thread::spawn(move || loop {
+                    let job = match receiver.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        Err(_) => return
+                    };
+                    if job.cancelled.load(Ordering::Relaxed) {
+                        continue;
+                    }
+                    run(job, &cache, &reports);
+                });
+            }
+            sender
+        })
+    }
+}
+
+/// Measures the entry of `job`, reporting its size so far every now and
+/// then, and the final one unless the job gets cancelled.
+fn run(job: Job, cache: &Mutex<HashMap<Key, Usage>>, reports: &Sender<Report>) {
+    let stat = match job.vfs.symlink_stat(&job.path) {
+        Ok(stat) => stat,
+        Err(_) => {
+            let _ = reports.send(Report { path: job.path, usage: Usage::default(), done: true });
+            return;
+        }
+    };
+
+    let key = stat.id.filter(|_| stat.is_dir).map(|id| Key { id, modified: stat.modified });
+    if let Some(usage) = key.and_then(|key| cache.lock().unwrap().get(&key).copied()) {
+        let _ = reports.send(Report { path: job.path, usage, done: true });
+        return;
+    }
+
+    let mut usage = Usage::default();
+    let mut seen = HashSet::new();
+    usage.add(&stat, &mut seen);
+
+    // Symlinks are counted as themselves, never followed.
+    let mut dirs = if stat.is_dir { vec![job.path.clone()] } else { vec![] };
+    let mut last_report = Instant::now();
+    while let Some(dir) = dirs.pop() {
+        let listing = match job.vfs.list(&dir) {
+            Ok(listing) => listing,
+            Err(_) => continue
+        };
+        for dir_entry in listing.flatten() {
+            if job.cancelled.load(Ordering::Relaxed) {
+                return;
+            }
+            let stat = match job.vfs.symlink_stat(&dir_entry.path) {
+                Ok(stat) => stat,
+                Err(_) => continue
+            };
+            usage.add(&stat, &mut seen);
+            if stat.is_dir {
+                dirs.push(dir_entry.path);
+            }
+
+            if last_report.elapsed() >= REPORT_INTERVAL {
+                let _ = reports.send(Report { path: job.path.clone(), usage, done: false });
+                last_report = Instant::now();
+            }
+        }
+    }
+
+    if let Some(key) = key {
+        cache.lock().unwrap().insert(key, usage);
+    }
+    let _ = reports.send(Report { path: job.path, usage, done: true });
+}
+
+/// Sizes in powers of 1024, e.g. `512B`, `3.4K` or `12.0G`.
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    #[test]
+    fn adds_up_a_tree_through_the_vfs() {
+        let memory = Memory::new();
+        memory.write(Path::new("/a/b.txt"), "12345").unwrap();
+        memory.write(Path::new("/a/c/d.txt"), "123").unwrap();
+        let vfs: Arc<dyn Vfs> = Arc::new(memory);
+
+        let mut du = DiskUsage::new();
+        du.measure(&vfs, &[PathBuf::from("/a"), PathBuf::from("/a/b.txt")]);
+        while du.is_busy() {
+            thread::sleep(Duration::from_millis(1));
+            du.poll();
+        }
+        assert_eq!(du.size(Path::new("/a"), true, true), Some(8));
+        assert_eq!(du.size(Path::new("/a/b.txt"), false, true), Some(5));
+        assert_eq!(du.label(Path::new("/a"), true, true), "8B");
+    }
+}
@@ -0,0 +1,155 @@
+use std::{cell::OnceCell, path::{Path, PathBuf}, rc::Rc, sync::Arc};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{color_config::ColorConfig, desktop::DesktopEntry, vfs::{DirEntry, Kind, Stat, Vfs}};
+
+/// A line of the buffer.
+pub enum Line {
+    /// Decorations such as the header, which are not backed by a path.
+    Text { len: usize, display: String },
+    /// The `..` and `.` links at the top of the listing.
+    Link { path: Option<PathBuf>, name: &'static str },
+    /// `guide` draws the tree from the entry up to its parent when the
+    /// entry is listed under an expanded directory, and is empty otherwise.
+    Entry { entry: Rc<Entry>, guide: String },
+}
+
+/// A file or directory read by the loader. Only what the listing returns
+/// for free is known up front; `metadata` is fetched the first time it is
+/// needed, which for most entries is when they scroll into view.
+pub struct Entry {
+    pub path: PathBuf,
+    pub name: String,
+    /// Width of `name` in graphemes.
+    pub width: usize,
+    kind: Option<Kind>,
+    metadata: OnceCell<Option<Stat>>,
+    desktop_name: OnceCell<Option<String>>,
+    /// Where the entry was listed, and its metadata is fetched from.
+    vfs: Arc<dyn Vfs>,
+}
+
+impl Line {
+    pub fn text(display: String) -> Line {
+        Line::Text { len: display.graphemes(true).count(), display }
+    }
+
+    pub fn path(&self) -> Option<&PathBuf> {
+        match self {
+            Line::Text { .. } => None,
+            Line::Link { path, .. } => path.as_ref(),
+            Line::Entry { entry, .. } => Some(&entry.path),
+        }
+    }
+
+    pub fn entry(&self) -> Option<&Rc<Entry>> {
+        match self {
+            Line::Entry { entry, .. } => Some(entry),
+            _ => None
+        }
+    }
+
+    /// Number of columns the cursor can move through on this line.
+    pub fn len(&self) -> usize {
+        match self {
+            Line::Text { len, .. } => *len,
+            Line::Link { name, .. } => name.len() + 1,
+            Line::Entry { entry, guide } => guide.chars().count() + entry.width + entry.is_dir() as usize,
+        }
+    }
+
+    pub fn display(&self, theme: &ColorConfig) -> String {
+        match self {
+            Line::Text { display, .. } => display.clone(),
+            Line::Link { name, .. } => format!("{}{}",
+                theme.paint(name, theme.dir_name_color),
+                theme.paint("/", theme.link_slash_color)
+            ),
+            Line::Entry { entry, guide } if entry.is_dir() => format!("{}{}{}",
+                guide,
+                theme.paint(&entry.name, theme.dir_name_color),
+                theme.paint("/", theme.forward_slash_color)
+            ),
+            Line::Entry { entry, guide } => format!("{}{}", guide, theme.paint(&entry.name, theme.file_name_color)),
+        }
+    }
+}
+
+impl Entry {
+    pub fn new(dir_entry: DirEntry, vfs: Arc<dyn Vfs>) -> Entry {
+        let name = dir_entry.path.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let entry = Entry {
+            width: name.graphemes(true).count(),
+            path: dir_entry.path,
+            name,
+            kind: dir_entry.kind,
+            metadata: OnceCell::new(),
+            desktop_name: OnceCell::new(),
+            vfs,
+        };
+        if let Some(stat) = dir_entry.stat {
+            let _ = entry.metadata.set(Some(stat));
+        }
+        entry
+    }
+
+    /// Names the entry by its path from `root`, as in a flattened listing.
+    pub fn relative_to(mut self, root: &Path) -> Entry {
+        if let Ok(relative) = self.path.strip_prefix(root) {
+            self.name = relative.to_string_lossy().to_string();
+            self.width = self.name.graphemes(true).count();
+        }
+        self
+    }
+
+    /// Fetches the metadata right away, for callers that will need it anyway.
+    pub fn with_metadata(self) -> Entry {
+        self.metadata();
+        self
+    }
+
+    /// Follows symlinks. Cached after the first call.
+    pub fn metadata(&self) -> Option<&Stat> {
+        self.metadata
+            .get_or_init(|| self.vfs.stat(&self.path).ok())
+            .as_ref()
+    }
+
+    pub fn has_metadata(&self) -> bool {
+        self.metadata.get().is_some()
+    }
+
+    /// The application name of a `.desktop` file, read the first time it
+    /// is asked for.
+    pub fn desktop_name(&self) -> Option<&str> {
+        self.desktop_name
+            .get_or_init(|| {
+                if self.path.extension().is_none_or(|extension| extension != "desktop") {
+                    return None;
+                }
+                let text = self.vfs.read(&self.path).ok()?;
+                DesktopEntry::parse(&String::from_utf8_lossy(&text)).ok()?.name
+            })
+            .as_deref()
+    }
+
+    pub fn is_dir(&self) -> bool {
+        match self.kind {
+            Some(Kind::Dir) => true,
+            Some(Kind::File) => false,
+            _ => self.metadata().is_some_and(|metadata| metadata.is_dir)
+        }
+    }
+
+    /// Same as `is_dir`, without fetching metadata for symlinks. Used to
+    /// group directories when sorting, which looks at every entry.
+    pub fn is_dir_hint(&self) -> bool {
+        if let Some(Some(metadata)) = self.metadata.get() {
+            return metadata.is_dir;
+        }
+        self.kind == Some(Kind::Dir)
+    }
+}
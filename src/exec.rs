@@ -0,0 +1,34 @@
+use std::{fs::File, io::Read, path::{Path, PathBuf}};
+
+/// Describes what runs the executable at `path`: the `#!` line of a script,
+/// or the kind of binary.
+pub fn interpreter(path: &Path) -> String {
+    let mut head = [0; 256];
+    let len = match File::open(path).and_then(|mut file| file.read(&mut head)) {
+        Ok(len) => len,
+        Err(e) => return format!("cannot be read: {}", e)
+    };
+    let head = &head[..len];
+
+    if let Some(shebang) = head.strip_prefix(b"#!") {
+        let line = shebang.split(|&byte| byte == b'\n').next().unwrap_or_default();
+        return format!("script run by {}", String::from_utf8_lossy(line).trim());
+    }
+    if head.starts_with(b"\x7fELF") {
+        return "ELF binary".to_string();
+    }
+    "no #! line, run by sh".to_string()
+}
+
+/// Whether `path` is inside one of the `trusted` directories. Symlinks are
+/// resolved first, so that a link cannot smuggle a program in.
+pub fn is_trusted(path: &Path, trusted: &[PathBuf]) -> bool {
+    let path = match path.canonicalize() {
+        Ok(path) => path,
+        Err(_) => return false
+    };
+    trusted.iter().any(|dir| {
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.clone());
+        path.starts_with(dir)
+    })
+}
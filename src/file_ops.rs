@@ -0,0 +1,123 @@
+use std::{fs, io, path::{Path, PathBuf}};
+
+use crate::vfs::Vfs;
+
+/// Copies a file or a whole directory tree into the directory `dest`,
+/// keeping its name. Returns the path of the copy.
+pub fn copy_into(vfs: &dyn Vfs, source: &Path, dest: &Path) -> io::Result<PathBuf> {
+    let target = target_path(vfs, source, dest)?;
+    vfs.copy(source, &target)?;
+    Ok(target)
+}
+
+/// Moves a file or directory into the directory `dest`, falling back to a
+/// copy and remove when `dest` is on another filesystem.
+pub fn move_into(vfs: &dyn Vfs, source: &Path, dest: &Path) -> io::Result<PathBuf> {
+    let target = target_path(vfs, source, dest)?;
+    match vfs.rename(source, &target) {
+        Ok(()) => (),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            vfs.copy(source, &target)?;
+            vfs.remove(source)?;
+        },
+        Err(e) => return Err(e)
+    }
+    Ok(target)
+}
+
+/// Removes a file or a whole directory tree from the local filesystem.
+pub fn remove(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        return fs::remove_dir_all(path);
+    }
+    fs::remove_file(path)
+}
+
+fn target_path(vfs: &dyn Vfs, source: &Path, dest: &Path) -> io::Result<PathBuf> {
+    let name = source.file_name().ok_or(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("cannot copy {}", source.display())
+    ))?;
+    let target = dest.join(name);
+
+    if vfs.stat(&target).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", target.display())
+        ));
+    }
+    if dest.starts_with(source) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("cannot copy {} into itself", source.display())
+        ));
+    }
+
+    Ok(target)
+}
+
+/// Copies a file or a whole directory tree on the local filesystem,
+/// keeping symlinks as they are.
+pub fn copy_recursive(source: &Path, target: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(source)?;
+
+    if metadata.file_type().is_symlink() {
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(fs::read_link(source)?, target)?;
+        #[cfg(not(unix))]
+        fs::copy(source, target)?;
+    } else if metadata.is_dir() {
+        fs::create_dir(target)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &target.join(entry.file_name()))?;
+        }
+        fs::set_permissions(target, metadata.permissions())?;
+    } else {
+        fs::copy(source, target)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    /// `/src` holding a file and a directory with a file, and an empty
+    /// `/dest`.
+    fn tree() -> Memory {
+        let vfs = Memory::new();
+        vfs.write(Path::new("/src/a.txt"), "a").unwrap();
+        vfs.write(Path::new("/src/sub/b.txt"), "b").unwrap();
+        vfs.create_dir(Path::new("/dest")).unwrap();
+        vfs
+    }
+
+    #[test]
+    fn copies_a_tree_into_a_dir() {
+        let vfs = tree();
+
+        let copy = copy_into(&vfs, Path::new("/src"), Path::new("/dest")).unwrap();
+        assert_eq!(copy, Path::new("/dest/src"));
+        assert_eq!(vfs.read(Path::new("/dest/src/sub/b.txt")).unwrap(), b"b");
+        assert_eq!(vfs.read(Path::new("/src/sub/b.txt")).unwrap(), b"b");
+
+        let e = copy_into(&vfs, Path::new("/src"), Path::new("/dest")).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+        let e = copy_into(&vfs, Path::new("/src"), Path::new("/src/sub")).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn moves_a_file_into_a_dir() {
+        let vfs = tree();
+
+        let moved = move_into(&vfs, Path::new("/src/a.txt"), Path::new("/dest")).unwrap();
+        assert_eq!(moved, Path::new("/dest/a.txt"));
+        assert_eq!(vfs.read(Path::new("/dest/a.txt")).unwrap(), b"a");
+        assert!(vfs.stat(Path::new("/src/a.txt")).is_err());
+        assert!(vfs.stat(Path::new("/src/sub/b.txt")).is_ok());
+    }
+}
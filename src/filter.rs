@@ -0,0 +1,110 @@
+use crate::settings::FilterMode;
+
+/// A compiled filter pattern. Matching ignores case unless the pattern has
+/// an uppercase letter, like vim's smartcase.
+pub struct Filter {
+    pattern: Pattern,
+    case_sensitive: bool,
+}
+
+enum Pattern {
+    Substring(String),
+    Glob(glob::Pattern),
+    Fuzzy(Vec<char>),
+}
+
+impl Filter {
+    pub fn new(pattern: &str, mode: FilterMode) -> Filter {
+        let case_sensitive = pattern.chars().any(char::is_uppercase);
+        let folded = if case_sensitive { pattern.to_string() } else { pattern.to_lowercase() };
+
+        let is_glob = match mode {
+            FilterMode::Auto => pattern.contains(['*', '?', '[']),
+            FilterMode::Glob => true,
+            FilterMode::Substring | FilterMode::Fuzzy => false,
+        };
+        let pattern = match mode {
+            FilterMode::Fuzzy => Pattern::Fuzzy(folded.chars().collect()),
+            // A glob that does not parse yet, e.g. while `[` is being typed,
+            // matches as a substring until it does.
+            _ if is_glob => match glob::Pattern::new(pattern) {
+                Ok(glob) => Pattern::Glob(glob),
+                Err(_) => Pattern::Substring(folded)
+            },
+            _ => Pattern::Substring(folded),
+        };
+
+        Filter { pattern, case_sensitive }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        match &self.pattern {
+            Pattern::Glob(glob) => glob.matches_with(name, glob::MatchOptions {
+                case_sensitive: self.case_sensitive,
+                require_literal_separator: false,
+                require_literal_leading_dot: false,
+            }),
+            Pattern::Substring(pattern) => self.fold(name).contains(pattern.as_str()),
+            Pattern::Fuzzy(pattern) => {
+                let name = self.fold(name);
+                let mut chars = name.chars();
+                pattern.iter().all(|&wanted| chars.any(|c| c == wanted))
+            },
+        }
+    }
+
+    fn fold(&self, name: &str) -> String {
+        if self.case_sensitive {
+            return name.to_string();
+        }
+        name.to_lowercase()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, mode: FilterMode, name: &str) -> bool {
+        Filter::new(pattern, mode).matches(name)
+    }
+
+    #[test]
+    fn matches_substrings() {
+        assert!(matches("am m", FilterMode::Auto, "gam ma"));
+        assert!(matches("main", FilterMode::Substring, "src/main.rs"));
+        assert!(!matches("gm", FilterMode::Substring, "gam ma"));
+        assert!(matches("*.rs", FilterMode::Substring, "*.rs notes"));
+        assert!(!matches("*.rs", FilterMode::Substring, "main.rs"));
+    }
+
+    #[test]
+    fn matches_globs() {
+        assert!(matches("*.rs", FilterMode::Auto, "main.rs"));
+        assert!(!matches("*.rs", FilterMode::Auto, "main.rs.bak"));
+        assert!(matches("?.txt", FilterMode::Auto, "a.txt"));
+        assert!(matches("main", FilterMode::Glob, "main"));
+        assert!(!matches("main", FilterMode::Glob, "main.rs"));
+        // Not a glob yet while `[` is being typed.
+        assert!(matches("[a", FilterMode::Auto, "x[ay"));
+    }
+
+    #[test]
+    fn matches_fuzzily() {
+        assert!(matches("gm", FilterMode::Fuzzy, "gam ma"));
+        assert!(matches("mrs", FilterMode::Fuzzy, "main.rs"));
+        assert!(!matches("mg", FilterMode::Fuzzy, "gam"));
+        assert!(!matches("gmm", FilterMode::Fuzzy, "gam"));
+    }
+
+    #[test]
+    fn ignores_case_unless_the_pattern_has_uppercase() {
+        assert!(matches("readme", FilterMode::Auto, "README.md"));
+        assert!(matches("*.MD", FilterMode::Auto, "README.MD"));
+        assert!(!matches("*.MD", FilterMode::Auto, "readme.md"));
+        assert!(matches("*.md", FilterMode::Auto, "README.MD"));
+        assert!(!matches("Read", FilterMode::Substring, "readme"));
+        assert!(matches("RM", FilterMode::Fuzzy, "README"));
+        assert!(!matches("RM", FilterMode::Fuzzy, "readme"));
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    collections::VecDeque, io, path::PathBuf,
+    sync::{atomic::{AtomicBool, Ordering}, mpsc::{self, Receiver, RecvTimeoutError, TryRecvError}, Arc},
+    thread, time::{Duration, Instant}
+};
+
+use crate::{entry::Entry, vfs::{Kind, Vfs}};
+
+/// How often the loader hands what it has read so far to the UI.
+const BATCH_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Lists a directory of a `Vfs` on a background thread, so that huge
+/// directories or slow mounts never block the UI.
+/// Dropping the loader cancels it.
+pub struct Loader {
+    receiver: Receiver<Message>,
+    cancelled: Arc<AtomicBool>,
+    /// Number of entries read so far.
+    pub count: usize,
+    /// Whether entries are handed out as they are read, rather than all
+    /// at once when the whole directory has been read.
+    pub progressive: bool,
+    held: Vec<Entry>,
+}
+
+/// How the loader walks down from the directory it reads, to flatten the
+/// tree under it into a single listing.
+pub struct Walk {
+    /// Levels of directories listed, 1 being the directory itself. The
+    /// directories of the last level are listed rather than walked into.
+    pub depth: usize,
+    /// Whether an entry, by name, is neither listed nor walked into.
+    pub hides: Box<dyn Fn(&str) -> bool + Send>,
+}
+
+enum Message {
+    Batch(Vec<Entry>),
+    Failed(io::Error),
+    Done,
+}
+
+/// What arrived from the loader since it was last polled.
+pub struct Progress {
+    pub entries: Vec<Entry>,
+    pub error: Option<io::Error>,
+    pub done: bool,
+}
+
+impl Loader {
+    /// Starts reading `dir`, or the tree under it with a `walk`, in which
+    /// case entries are named by their path from `dir`. Symlinks are never
+    /// walked into, and subdirectories that cannot be read are skipped.
+    /// With `with_metadata`, every entry is also stat-ed on the loader
+    /// thread, which sorting by size or time needs.
+    pub fn spawn(
+        vfs: Arc<dyn Vfs>, dir: PathBuf, with_metadata: bool, progressive: bool, walk: Option<Walk>
+    ) -> Loader {
+        let (sender, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let cancelled_copy = cancelled.clone();
+
+        thread::spawn(move || {
+            let mut batch = vec![];
+            let mut last_sent = Instant::now();
+            let mut dirs = VecDeque::from([(dir.clone(), 1)]);
+            while let Some((current, level)) = dirs.pop_front() {
+                let listing = match vfs.list(&current) {
+                    Ok(listing) => listing,
+                    Err(e) if current == dir => {
+                        let _ = sender.send(Message::Failed(e));
+                        return;
+                    },
+                    Err(_) => continue
+                };
+
+                for dir_entry in listing {
+                    if cancelled_copy.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    let dir_entry = match dir_entry {
+                        Ok(dir_entry) => dir_entry,
+                        Err(_) => continue
+                    };
+
+                    let mut entry = match &walk {
+                        Some(walk) => {
+                            let name = dir_entry.path.file_name().unwrap_or_default().to_string_lossy();
+                            if (walk.hides)(&name) {
+                                continue;
+                            }
+                            let is_dir = match dir_entry.kind {
+                                Some(kind) => kind == Kind::Dir,
+                                None => dir_entry.is_dir(vfs.as_ref())
+                            };
+                            if is_dir && level < walk.depth {
+                                dirs.push_back((dir_entry.path, level + 1));
+                                continue;
+                            }
+                            Entry::new(dir_entry, vfs.clone()).relative_to(&dir)
+                        },
+                        None => Entry::new(dir_entry, vfs.clone())
+                    };
+                    if with_metadata {
+                        entry = entry.with_metadata();
+                    }
+                    batch.push(entry);
+
+                    if last_sent.elapsed() >= BATCH_INTERVAL {
+                        if sender.send(Message::Batch(std::mem::take(&mut batch))).is_err() {
+                            return;
+                        }
+                        last_sent = Instant::now();
+                    }
+                }
+            }
+
+            let _ = sender.send(Message::Batch(batch));
+            let _ = sender.send(Message::Done);
+        });
+
+        Loader { receiver, cancelled, count: 0, progressive, held: vec![] }
+    }
+
+    /// Collects whatever the loader sent, without blocking.
+    pub fn poll(&mut self) -> Progress {
+        self.collect(None)
+    }
+
+    /// Collects what the loader sends within `timeout`, returning early if
+    /// it finishes. Small directories are then shown complete right away.
+    pub fn wait(&mut self, timeout: Duration) -> Progress {
+        self.collect(Some(Instant::now() + timeout))
+    }
+
+    fn collect(&mut self, deadline: Option<Instant>) -> Progress {
+        let mut progress = Progress { entries: vec![], error: None, done: false };
+
+        loop {
+            let message = match deadline {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    self.receiver.recv_timeout(timeout).map_err(|e| e == RecvTimeoutError::Disconnected)
+                },
+                None => self.receiver.try_recv().map_err(|e| e == TryRecvError::Disconnected)
+            };
+
+            match message {
+                Ok(Message::Batch(batch)) => {
+                    self.count += batch.len();
+                    self.held.extend(batch);
+                },
+                Ok(Message::Failed(e)) => {
+                    progress.error = Some(e);
+                    progress.done = true;
+                    break;
+                },
+                Ok(Message::Done) | Err(true) => {
+                    progress.done = true;
+                    break;
+                },
+                Err(false) => break
+            }
+        }
+
+        if self.progressive || progress.done {
+            progress.entries = std::mem::take(&mut self.held);
+        }
+        progress
+    }
+}
+
+impl Drop for Loader {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
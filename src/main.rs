@@ -1,6 +1,10 @@
-use std::io::{self, Write};
+use std::{io::{self, Write}, path::Path};
 
 use app::App;
+use cli::{Invocation, Options};
+use color_config::ColorConfig;
+use openers::Openers;
+use picker::{Picker, Select};
 use crossterm::{
     cursor::MoveTo,
     terminal::{
@@ -11,22 +15,72 @@ use crossterm::{
     },
     execute
 };
+use path_absolutize::Absolutize;
 
 mod app;
+mod archive;
+mod cli;
 mod color_config;
+mod command_line;
+mod commands;
+mod config;
+mod desktop;
+mod du;
+mod entry;
+mod exec;
+mod file_ops;
+mod filter;
+mod loader;
+#[cfg(test)]
+mod memory;
+mod menu;
+mod openers;
+mod pager;
 mod panic_guard;
+mod picker;
+mod render;
+mod settings;
+mod shell_init;
+mod tab;
+mod tree;
+mod vfs;
+mod watcher;
 
 use panic_guard::GuardWithHook;
 
-use crate::app::LEFT_SIDE_PADDING;
-
 const BOTTOM_RESERVED: u16 = 3;
 const START_X: u16 = 0;
 const START_Y: u16 = 3;
 
 fn main() -> io::Result<()> {
+    let options = match cli::parse(std::env::args().skip(1)) {
+        Ok(Invocation::Run(options)) => *options,
+        Ok(Invocation::Init { shell, bind }) => {
+            match shell_init::script(&shell, bind) {
+                Ok(script) => print!("{}", script),
+                Err(e) if e.kind() == io::ErrorKind::InvalidInput => usage_error(e.to_string()),
+                Err(e) => fail(e)
+            }
+            return Ok(());
+        },
+        Ok(Invocation::Help) => {
+            let _ = writeln!(io::stdout(), "{}", cli::USAGE);
+            return Ok(());
+        },
+        Ok(Invocation::Version) => {
+            println!("fap {}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        },
+        Err(msg) => usage_error(msg)
+    };
+
+    let cwd_file = options.cwd_file.clone();
+    let choose_files = options.choose_files.clone();
+    let separator = if options.print0 { b'\0' } else { b'\n' };
     let mut app = App::default();
-    app.output = app.cd.display().to_string();
+    if let Err(e) = setup(&mut app, options) {
+        fail(e);
+    }
 
     execute!(io::stderr(), 
         EnterAlternateScreen, 
@@ -38,6 +92,7 @@ fn main() -> io::Result<()> {
         let _guard = GuardWithHook::new(|| 
             execute!(io::stderr(), LeaveAlternateScreen, EnableLineWrap).unwrap()
         );
+        let _temp_guard = GuardWithHook::new(archive::remove_temp_dir);
 
         enable_raw_mode()?;
         {
@@ -46,23 +101,92 @@ fn main() -> io::Result<()> {
             io::stderr().flush()?;
             app.generate_buffer();
             app.draw_screen()?;
-            execute!(io::stderr(), MoveTo(LEFT_SIDE_PADDING + START_X, START_Y))?;
             app.read_input()?;
         }
     }
 
-    println!("{}", app.cd.display());
-    return Ok(());
+    let dir = match app.output {
+        Some(dir) => dir,
+        None => std::process::exit(1)
+    };
+    let written = match (choose_files, cwd_file) {
+        (Some(out), _) => {
+            let mut bytes = vec![];
+            for path in &app.chosen {
+                bytes.extend(path_bytes(path));
+                bytes.push(separator);
+            }
+            write_output(&out, &bytes)
+        },
+        // Written as is, without a trailing newline, so that any directory
+        // name can be read back.
+        (None, Some(out)) => write_output(&out, &path_bytes(&dir)),
+        (None, None) => writeln!(io::stdout(), "{}", dir.display())
+    };
+    if let Err(e) = written {
+        fail(e);
+    }
+    Ok(())
+}
+
+/// Writes `bytes` to the file `out`, or to stdout when `out` is `-`.
+fn write_output(out: &Path, bytes: &[u8]) -> io::Result<()> {
+    if out == Path::new("-") {
+        return io::stdout().write_all(bytes);
+    }
+    std::fs::write(out, bytes)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", out.display(), e)))
+}
+
+fn path_bytes(path: &Path) -> Vec<u8> {
+    #[cfg(unix)]
+    return std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str()).to_vec();
+    #[cfg(not(unix))]
+    return path.to_string_lossy().into_owned().into_bytes();
+}
+
+/// Applies the config file, then the command line options on top of it.
+fn setup(app: &mut App, options: Options) -> io::Result<()> {
+    config::load(app, options.config.as_deref())?;
+    app.openers = Openers::load()?;
+
+    if options.show_hidden {
+        app.settings.hidden = true;
+    }
+    if let Some(sort) = options.sort {
+        app.settings.sort = sort;
+    }
+    if let Some(theme) = &options.theme {
+        app.theme = ColorConfig::load(theme)?;
+    }
+    if options.no_color || std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        app.theme = ColorConfig::mono();
+    }
+    app.title = options.title;
+    if options.choose_files.is_some() {
+        app.picker = Some(Picker {
+            select: options.select.unwrap_or(Select::Files),
+            extensions: options.extensions,
+            glob: options.glob,
+        });
+    }
+    if let Some(dir) = options.dir {
+        std::fs::read_dir(&dir)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", dir.display(), e)))?;
+        app.cd = dir.absolutize()?.to_path_buf();
+    }
+
+    Ok(())
 }
 
-fn write_to_screen(msg: String) -> io::Result<()> {
-    write!(io::stderr(), "{}", msg)?;
-    return Ok(());
+fn usage_error(msg: String) -> ! {
+    eprintln!("fap: {}\n\n{}", msg, cli::USAGE);
+    std::process::exit(2);
 }
 
-fn writeln_to_screen(msg: String) -> io::Result<()> {
-    write!(io::stderr(), "{}\n\r", msg)?;
-    return Ok(());
+fn fail(e: io::Error) -> ! {
+    eprintln!("fap: {}", e);
+    std::process::exit(1);
 }
 
 pub struct Position {
@@ -0,0 +1,209 @@
+use std::{
+    collections::BTreeMap, io::{self, Cursor, Read}, path::{Path, PathBuf}, sync::Mutex
+};
+
+use crate::vfs::{DirEntry, Kind, Listing, Stat, Vfs};
+
+/// A filesystem that only lives in memory, to drive the app from tests
+/// without touching the disk. Starts out with an empty `/`.
+pub struct Memory {
+    nodes: Mutex<BTreeMap<PathBuf, Node>>,
+}
+
+#[derive(Clone)]
+enum Node {
+    Dir,
+    File(Vec<u8>),
+}
+
+impl Memory {
+    pub fn new() -> Memory {
+        Memory { nodes: Mutex::new(BTreeMap::from([(PathBuf::from("/"), Node::Dir)])) }
+    }
+
+    /// Creates or replaces the file at `path`, and any missing parents.
+    pub fn write(&self, path: &Path, contents: impl Into<Vec<u8>>) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            self.create_dir(parent)?;
+        }
+        let mut nodes = self.nodes.lock().unwrap();
+        if let Some(Node::Dir) = nodes.get(path) {
+            return Err(io::Error::new(io::ErrorKind::IsADirectory, path.display().to_string()));
+        }
+        nodes.insert(path.to_path_buf(), Node::File(contents.into()));
+        Ok(())
+    }
+
+    /// Copies the node at `from` and everything under it to `to`, and
+    /// returns the paths that were copied.
+    fn duplicate(nodes: &mut BTreeMap<PathBuf, Node>, from: &Path, to: &Path) -> io::Result<Vec<PathBuf>> {
+        if !nodes.contains_key(from) {
+            return Err(not_found(from));
+        }
+        if nodes.contains_key(to) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, to.display().to_string()));
+        }
+        if to.starts_with(from) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cannot copy {} into itself", from.display())
+            ));
+        }
+        match to.parent().and_then(|parent| nodes.get(parent)) {
+            Some(Node::Dir) => (),
+            _ => return Err(not_found(to.parent().unwrap_or(to)))
+        }
+
+        let copied: Vec<(PathBuf, Node)> = nodes.iter()
+            .filter(|(path, _)| path.starts_with(from))
+            .map(|(path, node)| (path.clone(), node.clone()))
+            .collect();
+        let mut paths = vec![];
+        for (path, node) in copied {
+            let copy = match path.strip_prefix(from) {
+                Ok(relative) if !relative.as_os_str().is_empty() => to.join(relative),
+                _ => to.to_path_buf()
+            };
+            nodes.insert(copy, node);
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+}
+
+impl Vfs for Memory {
+    fn list(&self, dir: &Path) -> io::Result<Listing> {
+        let nodes = self.nodes.lock().unwrap();
+        match nodes.get(dir) {
+            Some(Node::Dir) => (),
+            Some(Node::File(_)) => return Err(not_a_directory(dir)),
+            None => return Err(not_found(dir))
+        }
+
+        let entries: Vec<io::Result<DirEntry>> = nodes.iter()
+            .filter(|(path, _)| path.parent() == Some(dir))
+            .map(|(path, node)| Ok(DirEntry {
+                path: path.clone(),
+                kind: Some(match node {
+                    Node::Dir => Kind::Dir,
+                    Node::File(_) => Kind::File,
+                }),
+                stat: Some(node_stat(node)),
+            }))
+            .collect();
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Stat> {
+        return self.nodes.lock().unwrap().get(path).map(node_stat).ok_or_else(|| not_found(path));
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        return match self.nodes.lock().unwrap().get(path) {
+            Some(Node::File(contents)) => Ok(Box::new(Cursor::new(contents.clone()))),
+            Some(Node::Dir) => Err(io::Error::new(io::ErrorKind::IsADirectory, path.display().to_string())),
+            None => Err(not_found(path))
+        };
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut nodes = self.nodes.lock().unwrap();
+        for path in Memory::duplicate(&mut nodes, from, to)? {
+            nodes.remove(&path);
+        }
+        Ok(())
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        Memory::duplicate(&mut self.nodes.lock().unwrap(), from, to)?;
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        let mut nodes = self.nodes.lock().unwrap();
+        if path.parent().is_none() || !nodes.contains_key(path) {
+            return Err(not_found(path));
+        }
+        nodes.retain(|node, _| !node.starts_with(path));
+        Ok(())
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        let mut nodes = self.nodes.lock().unwrap();
+        for dir in path.ancestors().collect::<Vec<_>>().into_iter().rev() {
+            match nodes.get(dir) {
+                Some(Node::Dir) => (),
+                Some(Node::File(_)) => return Err(not_a_directory(dir)),
+                None => {
+                    nodes.insert(dir.to_path_buf(), Node::Dir);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn node_stat(node: &Node) -> Stat {
+    let len = match node {
+        Node::Dir => 0,
+        Node::File(contents) => contents.len() as u64,
+    };
+    Stat {
+        is_dir: matches!(node, Node::Dir),
+        len,
+        modified: None,
+        executable: false,
+        disk_len: len,
+        links: 1,
+        id: None,
+    }
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", path.display()))
+}
+
+fn not_a_directory(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotADirectory, format!("{} is not a directory", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(vfs: &Memory, dir: &str) -> Vec<String> {
+        let mut names: Vec<String> = vfs.list(Path::new(dir)).unwrap()
+            .map(|entry| entry.unwrap().path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn lists_what_is_right_inside_a_dir() {
+        let vfs = Memory::new();
+        vfs.write(Path::new("/a/b/c.txt"), "c").unwrap();
+        vfs.write(Path::new("/a/d.txt"), "dd").unwrap();
+        vfs.create_dir(Path::new("/a/e")).unwrap();
+
+        assert_eq!(names(&vfs, "/"), ["a"]);
+        assert_eq!(names(&vfs, "/a"), ["b", "d.txt", "e"]);
+        assert!(names(&vfs, "/a/e").is_empty());
+
+        let listed: Vec<DirEntry> = vfs.list(Path::new("/a")).unwrap().map(Result::unwrap).collect();
+        let d = listed.iter().find(|entry| entry.path == Path::new("/a/d.txt")).unwrap();
+        assert!(d.kind == Some(Kind::File));
+        assert_eq!(d.stat.as_ref().map(|stat| stat.len), Some(2));
+        assert!(listed.iter().find(|entry| entry.path == Path::new("/a/b")).unwrap().is_dir(&vfs));
+    }
+
+    #[test]
+    fn fails_to_list_what_is_not_a_dir() {
+        let vfs = Memory::new();
+        vfs.write(Path::new("/f"), "").unwrap();
+
+        assert_eq!(vfs.list(Path::new("/f")).err().map(|e| e.kind()), Some(io::ErrorKind::NotADirectory));
+        assert_eq!(vfs.list(Path::new("/g")).err().map(|e| e.kind()), Some(io::ErrorKind::NotFound));
+        assert!(vfs.create_dir(Path::new("/f/g")).is_err());
+    }
+}
@@ -0,0 +1,42 @@
+use std::cmp::min;
+
+use crate::{render::Frame, BOTTOM_RESERVED};
+
+/// Draws a menu of `items` over the bottom of the listing, just above the
+/// status lines, with `selected` highlighted. Items are numbered from 1 so
+/// that they can be picked with a digit.
+pub fn draw_menu(frame: &mut Frame, title: &str, items: &[String], selected: usize) {
+    let width = frame.width();
+    let bottom = frame.height().saturating_sub(BOTTOM_RESERVED);
+    if bottom == 0 {
+        return;
+    }
+    let shown = min(items.len(), bottom as usize - 1);
+    let top = bottom - shown as u16 - 1;
+
+    let title = format!("{:-<width$}", format!("{} ", title), width = width as usize);
+    frame.put_str(0, top, &title, width);
+    for (i, item) in items.iter().take(shown).enumerate() {
+        let line = format!("{:>3} {:<width$}", i + 1, item, width = width as usize);
+        let line = if i == selected { format!("\x1b[7m{}\x1b[0m", line) } else { line };
+        frame.put_str(0, top + 1 + i as u16, &line, width);
+    }
+}
+
+/// Draws `lines` over the bottom reserved rows and as many rows above them
+/// as needed, e.g. to ask for confirmation.
+pub fn draw_dialog(frame: &mut Frame, lines: &[String]) {
+    let width = frame.width();
+    let height = frame.height();
+    let rows = min(lines.len() as u16 + 1, height);
+    if rows == 0 {
+        return;
+    }
+    let top = height - rows;
+
+    frame.put_str(0, top, &format!("{:=<width$}", "", width = width as usize), width);
+    for (i, line) in lines.iter().take(rows as usize - 1).enumerate() {
+        let line = format!("{:<width$}", line, width = width as usize);
+        frame.put_str(0, top + 1 + i as u16, &line, width);
+    }
+}
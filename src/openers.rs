@@ -0,0 +1,173 @@
+use std::{io, path::{Path, PathBuf}};
+
+use crate::{commands, config, shell_init::posix_quote};
+
+/// Programs to open files with, read from `openers.toml`. Files that no
+/// rule matches are opened with the system default.
+#[derive(Default)]
+pub struct Openers {
+    pub rules: Vec<Rule>,
+}
+
+/// A `[[rule]]` of `openers.toml`. A rule matches a file when all of its
+/// conditions do; a rule without any matches every file.
+pub struct Rule {
+    pub name: Option<String>,
+    /// Extensions, without the dot.
+    pub extensions: Vec<String>,
+    /// Matched against the file name.
+    pub glob: Option<glob::Pattern>,
+    /// Matched against the MIME type guessed from the extension, e.g. `video/*`.
+    pub mime: Option<glob::Pattern>,
+    pub prefix: Option<PathBuf>,
+    /// Run by `sh -c`, with `{}` replaced by the quoted path. The path is
+    /// appended when there is no `{}`.
+    pub command: String,
+    /// Whether the command takes over the terminal, e.g. an editor, rather
+    /// than running detached, e.g. an image viewer.
+    pub foreground: bool,
+}
+
+impl Openers {
+    /// Reads `openers.toml` in `config_dir`, if it exists:
+    ///
+    /// ```toml
+    /// [[rule]]
+    /// ext = ["rs", "toml"]
+    /// command = "nvim {}"
+    /// foreground = true
+    ///
+    /// [[rule]]
+    /// mime = "video/*"
+    /// command = "mpv --fs {}"
+    /// ```
+    pub fn load() -> io::Result<Openers> {
+        let path = match config::config_dir().map(|dir| dir.join("openers.toml")) {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Openers::default())
+        };
+
+        std::fs::read_to_string(&path)
+            .and_then(|text| Openers::parse(&text))
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+    }
+
+    fn parse(text: &str) -> io::Result<Openers> {
+        let mut table: toml::Table = text.parse().map_err(|e: toml::de::Error| {
+            invalid(e.message().to_string())
+        })?;
+
+        let rules = match table.remove("rule") {
+            Some(toml::Value::Array(rules)) => rules,
+            Some(_) => return Err(invalid("rule must be an array of tables, i.e. [[rule]]".to_string())),
+            None => vec![]
+        };
+        if let Some(key) = table.keys().next() {
+            return Err(invalid(format!("unknown key: {}", key)));
+        }
+
+        let rules = rules.into_iter()
+            .enumerate()
+            .map(|(i, rule)| match rule {
+                toml::Value::Table(rule) => Rule::parse(rule),
+                _ => Err(invalid("rule must be a table".to_string()))
+            }.map_err(|e| invalid(format!("rule {}: {}", i + 1, e))))
+            .collect::<io::Result<Vec<Rule>>>()?;
+
+        Ok(Openers { rules })
+    }
+
+    /// Indices of the rules that match `path`, in the order they were written.
+    pub fn matching(&self, path: &Path) -> Vec<usize> {
+        (0..self.rules.len()).filter(|&i| self.rules[i].matches(path)).collect()
+    }
+}
+
+impl Rule {
+    fn parse(table: toml::Table) -> io::Result<Rule> {
+        let mut rule = Rule {
+            name: None,
+            extensions: vec![],
+            glob: None,
+            mime: None,
+            prefix: None,
+            command: String::new(),
+            foreground: false,
+        };
+
+        for (key, value) in table {
+            match (key.as_str(), value) {
+                ("name", toml::Value::String(name)) => rule.name = Some(name),
+                ("ext", toml::Value::String(extension)) => rule.extensions.push(extension),
+                ("ext", toml::Value::Array(extensions)) => {
+                    for extension in extensions {
+                        match extension {
+                            toml::Value::String(extension) => rule.extensions.push(extension),
+                            _ => return Err(invalid("ext must be a list of strings".to_string()))
+                        }
+                    }
+                },
+                ("glob", toml::Value::String(glob)) => rule.glob = Some(pattern(&glob)?),
+                ("mime", toml::Value::String(mime)) => rule.mime = Some(pattern(&mime)?),
+                ("prefix", toml::Value::String(prefix)) => {
+                    rule.prefix = Some(commands::resolve_path(Path::new("/"), &prefix));
+                },
+                ("command", toml::Value::String(command)) => rule.command = command,
+                ("foreground", toml::Value::Boolean(foreground)) => rule.foreground = foreground,
+                (key, value) => return Err(invalid(format!("invalid value for {}: {}", key, value)))
+            }
+        }
+        for extension in &mut rule.extensions {
+            *extension = extension.trim_start_matches('.').to_string();
+        }
+
+        if rule.command.is_empty() {
+            return Err(invalid("missing command".to_string()));
+        }
+        Ok(rule)
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        let extension = path.extension().and_then(|extension| extension.to_str());
+
+        let by_extension = self.extensions.is_empty()
+            || extension.is_some_and(|extension| {
+                self.extensions.iter().any(|e| e.eq_ignore_ascii_case(extension))
+            });
+        let by_glob = self.glob.as_ref().is_none_or(|glob| glob.matches(&name));
+        let by_mime = self.mime.as_ref().is_none_or(|mime| {
+            mime_guess::from_path(path).iter().any(|guess| mime.matches(guess.essence_str()))
+        });
+        let by_prefix = self.prefix.as_ref().is_none_or(|prefix| path.starts_with(prefix));
+
+        by_extension && by_glob && by_mime && by_prefix
+    }
+
+    /// The shell command that opens `path`.
+    pub fn command_line(&self, path: &Path) -> String {
+        let path = posix_quote(&path.to_string_lossy());
+        if self.command.contains("{}") {
+            return self.command.replace("{}", &path);
+        }
+        format!("{} {}", self.command, path)
+    }
+
+    /// How the rule is listed in the "open with" menu.
+    pub fn title(&self) -> String {
+        let title = self.name.as_deref().unwrap_or(&self.command);
+        if self.foreground {
+            return title.to_string();
+        }
+        format!("{} &", title)
+    }
+}
+
+fn pattern(pattern: &str) -> io::Result<glob::Pattern> {
+    glob::Pattern::new(pattern)
+        .map_err(|e| invalid(format!("invalid pattern {}: {}", pattern, e.msg)))
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,178 @@
+use std::{
+    io::{self, BufRead, BufReader, Read}, process::{Child, Command, ExitStatus, Stdio},
+    sync::mpsc::{self, Receiver, Sender}, thread
+};
+
+use crate::render::Frame;
+
+/// Lines of output that are kept. Older ones are dropped, so that a program
+/// which never stops printing cannot eat up all the memory.
+const MAX_LINES: usize = 10_000;
+
+/// A program run from fap, with its output collected on background threads
+/// and shown in a scrollable view. Dropping the pager kills the program,
+/// and anything it started, if it is still running.
+pub struct Pager {
+    pub title: String,
+    pub lines: Vec<String>,
+    /// Line shown on the first row of the view.
+    pub top: usize,
+    /// Whether the view scrolls along as output arrives.
+    pub follow: bool,
+    /// Number of rows available for output, as of the last `fit`.
+    pub rows: usize,
+    /// Set once the program exited.
+    pub status: Option<ExitStatus>,
+    child: Child,
+    receiver: Receiver<String>,
+}
+
+impl Pager {
+    /// Starts `command` with its stdin closed and both stdout and stderr
+    /// captured.
+    pub fn spawn(title: String, command: &mut Command) -> io::Result<Pager> {
+        // In a process group of its own, so that whatever it starts can be
+        // killed along with it.
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(command, 0);
+
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let (sender, receiver) = mpsc::channel();
+        if let Some(stdout) = child.stdout.take() {
+            read_lines(stdout, sender.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            read_lines(stderr, sender);
+        }
+
+        Ok(Pager {
+            title,
+            lines: vec![],
+            top: 0,
+            follow: true,
+            rows: 0,
+            status: None,
+            child,
+            receiver,
+        })
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.status.is_none()
+    }
+
+    /// Collects the output that arrived since the last call, and checks
+    /// whether the program exited. Returns whether anything changed.
+    pub fn poll(&mut self) -> bool {
+        let len = self.lines.len();
+        self.lines.extend(self.receiver.try_iter());
+        let mut changed = self.lines.len() != len;
+
+        if self.lines.len() > MAX_LINES {
+            let excess = self.lines.len() - MAX_LINES;
+            self.lines.drain(..excess);
+            self.top = self.top.saturating_sub(excess);
+        }
+
+        if self.status.is_none() {
+            if let Ok(Some(status)) = self.child.try_wait() {
+                self.status = Some(status);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Adapts the view to a frame `height` rows high.
+    pub fn fit(&mut self, height: u16) {
+        self.rows = height.saturating_sub(2) as usize;
+        let last_top = self.lines.len().saturating_sub(self.rows);
+        self.top = if self.follow { last_top } else { self.top.min(last_top) };
+    }
+
+    /// Scrolls by `delta` lines. Reaching the end makes the view follow the
+    /// output again.
+    pub fn scroll(&mut self, delta: isize) {
+        let last_top = self.lines.len().saturating_sub(self.rows);
+        self.top = self.top.saturating_add_signed(delta).min(last_top);
+        self.follow = self.top == last_top;
+    }
+
+    pub fn scroll_to_top(&mut self) {
+        self.top = 0;
+        self.follow = false;
+    }
+
+    pub fn scroll_to_end(&mut self) {
+        self.follow = true;
+        self.top = self.lines.len().saturating_sub(self.rows);
+    }
+
+    /// Draws the title, the visible output and a footer telling whether the
+    /// program is still running. `fit` must be called first.
+    pub fn draw(&self, frame: &mut Frame) {
+        let width = frame.width();
+
+        let title = format!("{:=<width$}", format!("{} ", self.title), width = width as usize);
+        frame.put_str(0, 0, &title, width);
+
+        for (row, line) in self.lines.iter().skip(self.top).take(self.rows).enumerate() {
+            frame.put_str(0, row as u16 + 1, line, width);
+        }
+
+        let state = match self.status {
+            Some(status) => format!("{}, q closes", status),
+            None => "running, q stops it".to_string(),
+        };
+        let shown = match self.lines.len() {
+            0 => "no output".to_string(),
+            len => format!("lines {}-{} of {}", self.top + 1, (self.top + self.rows).min(len), len),
+        };
+        let footer = format!("\x1b[7m{} ({})\x1b[0m", state, shown);
+        frame.put_str(0, frame.height().saturating_sub(1), &footer, width);
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        if self.status.is_none() {
+            #[cfg(unix)]
+            unsafe {
+                libc::kill(-(self.child.id() as libc::pid_t), libc::SIGKILL);
+            }
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// Sends the lines read from `output` over `sender` until it is closed.
+/// Tabs are expanded and other control characters dropped, so that the
+/// program cannot move the cursor or change colors.
+fn read_lines(output: impl Read + Send + 'static, sender: Sender<String>) {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(output);
+        let mut bytes = vec![];
+        loop {
+            bytes.clear();
+            match reader.read_until(b'\n', &mut bytes) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => ()
+            }
+
+            let line = String::from_utf8_lossy(&bytes)
+                .replace('\t', "    ")
+                .chars()
+                .filter(|c| !c.is_control())
+                .collect();
+            if sender.send(line).is_err() {
+                return;
+            }
+        }
+    });
+}
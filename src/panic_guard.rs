@@ -1,6 +1,6 @@
 use std::sync::{Arc, Mutex};
 
-type PanicHookType = (dyn for<'r, 's> Fn(&'r std::panic::PanicInfo<'s>) + Send + Sync + 'static);
+type PanicHookType = dyn for<'r, 's> Fn(&'r std::panic::PanicHookInfo<'s>) + Send + Sync + 'static;
 
 /// Custom scopeguard-like struct that wraps a panic hook function and a callback ("cleanup")
 /// function, and in the case of a panic, calls the callback *before* the wrapped panic hook (i.e.
@@ -0,0 +1,64 @@
+use std::path::Path;
+
+use crate::entry::Entry;
+use crate::vfs::Vfs;
+
+/// State of `--choose-files`, where fap is used by another program to pick
+/// files instead of opening them.
+pub struct Picker {
+    pub select: Select,
+    /// Extensions, without the dot, that files must have to be listed.
+    pub extensions: Vec<String>,
+    /// Pattern that the names of files must match to be listed.
+    pub glob: Option<glob::Pattern>,
+}
+
+/// Which kinds of entries can be picked.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Select {
+    Files,
+    Dirs,
+    Any,
+}
+
+impl Select {
+    pub const ALL: &'static [&'static str] = &["files", "dirs", "any"];
+
+    pub fn parse(kind: &str) -> Option<Select> {
+        match kind {
+            "files" => Some(Select::Files),
+            "dirs" => Some(Select::Dirs),
+            "any" => Some(Select::Any),
+            _ => None
+        }
+    }
+}
+
+impl Picker {
+    /// Whether `entry` is listed. Directories always are, so that they can
+    /// still be entered.
+    pub fn shows(&self, entry: &Entry) -> bool {
+        if entry.is_dir() {
+            return true;
+        }
+        if self.select == Select::Dirs {
+            return false;
+        }
+
+        let extension = entry.path.extension().and_then(|extension| extension.to_str());
+        let by_extension = self.extensions.is_empty()
+            || extension.is_some_and(|extension| self.extensions.iter().any(|e| e == extension));
+        let by_glob = self.glob.as_ref().is_none_or(|glob| glob.matches(&entry.name));
+        by_extension && by_glob
+    }
+
+    /// Whether `path` on `vfs` can be picked.
+    pub fn accepts(&self, vfs: &dyn Vfs, path: &Path) -> bool {
+        let is_dir = vfs.stat(path).is_ok_and(|stat| stat.is_dir);
+        match self.select {
+            Select::Files => !is_dir,
+            Select::Dirs => is_dir,
+            Select::Any => true
+        }
+    }
+}
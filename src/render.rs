@@ -0,0 +1,186 @@
+use std::{cmp::min, io::{self, Write}};
+
+use crossterm::{
+    cursor::MoveTo,
+    style::ResetColor,
+    terminal::{self, Clear, ClearType},
+    queue
+};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// One screen worth of cells. Everything on screen is first drawn into a
+/// frame, which the `Renderer` then compares against the previous one.
+pub struct Frame {
+    width: u16,
+    height: u16,
+    cells: Vec<Cell>,
+}
+
+#[derive(Clone, PartialEq)]
+struct Cell {
+    /// Empty in the cells that a wide grapheme to the left spills over.
+    symbol: String,
+    /// The escape sequences in effect for this cell, since the last reset.
+    style: String,
+}
+
+/// Keeps the last frame that was written to the terminal, so that only the
+/// cells which changed since are written again.
+pub struct Renderer {
+    previous: Option<Frame>,
+}
+
+impl Frame {
+    pub fn new(width: u16, height: u16) -> Frame {
+        Frame {
+            width,
+            height,
+            cells: vec![Cell::blank(); width as usize * height as usize],
+        }
+    }
+
+    /// Creates a frame the size of the terminal.
+    pub fn for_terminal() -> io::Result<Frame> {
+        let size = terminal::window_size()?;
+        Ok(Frame::new(size.columns, size.rows))
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Writes `text` at (`x`, `y`), cut off after `max_width` columns or at
+    /// the edge of the frame. Wide graphemes, such as CJK or emoji, take two
+    /// columns. Escape sequences in `text` style the cells
+    /// after them, instead of taking up space. Returns the width written.
+    pub fn put_str(&mut self, x: u16, y: u16, text: &str, max_width: u16) -> u16 {
+        if y >= self.height {
+            return 0;
+        }
+
+        let end = min(x.saturating_add(max_width), self.width);
+        let mut col = x;
+        let mut style = String::new();
+        let mut escape = String::new();
+
+        for grapheme in text.graphemes(true) {
+            if grapheme == "\x1b" || !escape.is_empty() {
+                escape += grapheme;
+                if escape.len() > 1 && grapheme.chars().all(|c| c.is_ascii_alphabetic()) {
+                    if escape == "\x1b[0m" || escape == "\x1b[m" {
+                        style.clear();
+                    } else {
+                        style += &escape;
+                    }
+                    escape.clear();
+                }
+                continue;
+            }
+            let width = symbol_width(grapheme);
+            if col + width > end {
+                break;
+            }
+
+            let row = y as usize * self.width as usize;
+            let cell = &mut self.cells[row + col as usize];
+            cell.symbol = grapheme.to_string();
+            cell.style = style.clone();
+            for spilled in 1..width {
+                self.cells[row + (col + spilled) as usize] = Cell { symbol: String::new(), style: style.clone() };
+            }
+            col += width;
+        }
+
+        col - x
+    }
+}
+
+impl Cell {
+    fn blank() -> Cell {
+        Cell {
+            symbol: " ".to_string(),
+            style: String::new(),
+        }
+    }
+}
+
+impl Renderer {
+    pub fn new() -> Renderer {
+        Renderer { previous: None }
+    }
+
+    /// Forgets what is on screen, so the next frame is drawn in full. Needed
+    /// whenever something other than the renderer writes to the terminal.
+    pub fn invalidate(&mut self) {
+        self.previous = None;
+    }
+
+    /// Writes the cells of `frame` that changed since the last frame, then
+    /// moves the cursor to `cursor`. Everything goes out in a single flush.
+    pub fn render(&mut self, frame: Frame, cursor: (u16, u16)) -> io::Result<()> {
+        let mut out = io::stderr().lock();
+
+        let previous = match self.previous.take() {
+            Some(previous) if previous.width == frame.width && previous.height == frame.height => {
+                Some(previous)
+            },
+            _ => {
+                queue!(out, ResetColor, Clear(ClearType::All))?;
+                None
+            }
+        };
+
+        let mut style = String::new();
+        let mut next_position = None;
+        for (i, cell) in frame.cells.iter().enumerate() {
+            let unchanged = match &previous {
+                Some(previous) => previous.cells[i] == *cell,
+                None => *cell == Cell::blank()
+            };
+            // Spilled over cells are written along with the grapheme to
+            // their left, which changes whenever they do.
+            if unchanged || cell.symbol.is_empty() {
+                continue;
+            }
+
+            let position = ((i % frame.width as usize) as u16, (i / frame.width as usize) as u16);
+            if next_position != Some(position) {
+                queue!(out, MoveTo(position.0, position.1))?;
+            }
+            if cell.style != style {
+                queue!(out, ResetColor)?;
+                write!(out, "{}", cell.style)?;
+                style = cell.style.clone();
+            }
+            write!(out, "{}", cell.symbol)?;
+            next_position = Some((position.0 + symbol_width(&cell.symbol), position.1))
+                .filter(|(x, _)| *x < frame.width);
+        }
+
+        if !style.is_empty() {
+            queue!(out, ResetColor)?;
+        }
+        queue!(out, MoveTo(cursor.0, cursor.1))?;
+        out.flush()?;
+
+        self.previous = Some(frame);
+        Ok(())
+    }
+}
+
+/// Columns taken on screen by a grapheme. Zero width ones, which only come
+/// alone when they are malformed, still take a column of their own.
+fn symbol_width(grapheme: &str) -> u16 {
+    grapheme.width().clamp(1, 2) as u16
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Renderer::new()
+    }
+}
@@ -0,0 +1,323 @@
+use std::{fmt::Display, io, path::{Path, PathBuf}};
+
+use crate::commands;
+
+/// Names of every option understood by `:set`, used for completion.
+pub const OPTIONS: &[&str] = &["hidden", "sort", "reverse", "dirsfirst", "desktopnames", "exec", "trusted", "du", "apparent", "dedup", "filtermode", "treedepth", "ignore"];
+
+#[derive(Clone)]
+pub struct Settings {
+    pub hidden: bool,
+    pub sort: SortKey,
+    pub reverse: bool,
+    pub dirs_first: bool,
+    /// Whether `.desktop` files are listed with the name of the application.
+    pub desktop_names: bool,
+    pub exec: ExecPolicy,
+    /// Directories whose executables run without asking under
+    /// `ExecPolicy::Trusted`.
+    pub trusted: Vec<PathBuf>,
+    /// Whether entries are listed with their disk usage, whole directory
+    /// trees included.
+    pub du: bool,
+    /// Whether sizes are the apparent ones, i.e. the length of files,
+    /// rather than the space allocated for them on disk.
+    pub apparent: bool,
+    /// Whether files with several hard links are counted once.
+    pub dedup: bool,
+    pub filter_mode: FilterMode,
+    /// How many levels deep `zR` expands directories.
+    pub tree_depth: usize,
+    /// Names hidden along with dotfiles, e.g. `target` or `*.o`.
+    pub ignore: Vec<glob::Pattern>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SortKey {
+    Name,
+    Size,
+    Mtime,
+    Ext,
+}
+
+/// What happens when an executable is opened.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ExecPolicy {
+    /// Ask before running anything.
+    Ask,
+    /// Ask, unless the executable is in one of the trusted directories.
+    Trusted,
+    /// Never run anything, open executables as text instead.
+    Never,
+}
+
+/// How the pattern of a filter matches names.
+#[derive(Clone, Copy, PartialEq)]
+pub enum FilterMode {
+    /// As a glob when the pattern has any of `*?[`, as a substring otherwise.
+    Auto,
+    Substring,
+    Glob,
+    /// The characters of the pattern appear in the name in the same order.
+    Fuzzy,
+}
+
+impl FilterMode {
+    pub const ALL: &'static [&'static str] = &["auto", "substring", "glob", "fuzzy"];
+
+    pub fn parse(mode: &str) -> io::Result<FilterMode> {
+        match mode {
+            "auto" => Ok(FilterMode::Auto),
+            "substring" => Ok(FilterMode::Substring),
+            "glob" => Ok(FilterMode::Glob),
+            "fuzzy" => Ok(FilterMode::Fuzzy),
+            _ => Err(invalid_value("filtermode", mode))
+        }
+    }
+}
+
+impl ExecPolicy {
+    pub const ALL: &'static [&'static str] = &["ask", "trusted", "never"];
+
+    pub fn parse(policy: &str) -> io::Result<ExecPolicy> {
+        match policy {
+            "ask" => Ok(ExecPolicy::Ask),
+            "trusted" => Ok(ExecPolicy::Trusted),
+            "never" => Ok(ExecPolicy::Never),
+            _ => Err(invalid_value("exec", policy))
+        }
+    }
+}
+
+impl SortKey {
+    pub const ALL: &'static [&'static str] = &["name", "size", "mtime", "ext"];
+
+    pub fn parse(key: &str) -> io::Result<SortKey> {
+        match key {
+            "name" => Ok(SortKey::Name),
+            "size" => Ok(SortKey::Size),
+            "mtime" | "time" => Ok(SortKey::Mtime),
+            "ext" | "extension" => Ok(SortKey::Ext),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown sort key: {}", key)
+            ))
+        }
+    }
+
+    /// Whether sorting by this key looks at the metadata of every entry.
+    pub fn needs_metadata(self) -> bool {
+        matches!(self, SortKey::Size | SortKey::Mtime)
+    }
+}
+
+impl Settings {
+    /// Applies a single `:set` argument, following vim's syntax:
+    /// `opt`, `noopt`, `opt!` and `opt=value`.
+    pub fn set(&mut self, arg: &str) -> io::Result<()> {
+        if let Some((name, value)) = arg.split_once('=') {
+            return match name {
+                "sort" => {
+                    self.sort = SortKey::parse(value)?;
+                    Ok(())
+                },
+                "exec" => {
+                    self.exec = ExecPolicy::parse(value)?;
+                    Ok(())
+                },
+                "filtermode" => {
+                    self.filter_mode = FilterMode::parse(value)?;
+                    Ok(())
+                },
+                "treedepth" => {
+                    self.tree_depth = value.parse().map_err(|_| invalid_value(name, value))?;
+                    Ok(())
+                },
+                "ignore" => {
+                    self.ignore = value.split(',')
+                        .filter(|pattern| !pattern.is_empty())
+                        .map(glob::Pattern::new)
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| invalid_value(name, value))?;
+                    Ok(())
+                },
+                "trusted" => {
+                    self.trusted = value.split(':')
+                        .filter(|dir| !dir.is_empty())
+                        .map(|dir| commands::resolve_path(Path::new("/"), dir))
+                        .collect();
+                    Ok(())
+                },
+                _ => match parse_bool(value) {
+                    Some(value) => self.set_flag(name, |_| value),
+                    None => Err(invalid_value(name, value))
+                }
+            };
+        }
+
+        if let Some(name) = arg.strip_suffix('!') {
+            return self.set_flag(name, |flag| !flag);
+        }
+
+        if let Some(name) = arg.strip_prefix("no") {
+            if self.flag(name).is_some() {
+                return self.set_flag(name, |_| false);
+            }
+        }
+
+        self.set_flag(arg, |_| true)
+    }
+
+    /// Renders the current value of an option as `:set` would accept it.
+    pub fn get(&self, name: &str) -> io::Result<String> {
+        if name == "sort" {
+            return Ok(format!("sort={}", self.sort));
+        }
+        if name == "exec" {
+            return Ok(format!("exec={}", self.exec));
+        }
+        if name == "filtermode" {
+            return Ok(format!("filtermode={}", self.filter_mode));
+        }
+        if name == "treedepth" {
+            return Ok(format!("treedepth={}", self.tree_depth));
+        }
+        if name == "ignore" {
+            let patterns: Vec<&str> = self.ignore.iter().map(glob::Pattern::as_str).collect();
+            return Ok(format!("ignore={}", patterns.join(",")));
+        }
+        if name == "trusted" {
+            let dirs: Vec<String> = self.trusted.iter().map(|dir| dir.display().to_string()).collect();
+            return Ok(format!("trusted={}", dirs.join(":")));
+        }
+
+        match self.flag(name) {
+            Some(true) => Ok(name.to_string()),
+            Some(false) => Ok(format!("no{}", name)),
+            None => Err(unknown_option(name))
+        }
+    }
+
+    /// Whether entries named `name` are hidden, which dotfiles and names
+    /// matching `ignore` are unless `hidden` is set.
+    pub fn hides(&self, name: &str) -> bool {
+        !self.hidden
+            && (name.starts_with('.') || self.ignore.iter().any(|pattern| pattern.matches(name)))
+    }
+
+    pub fn is_flag(&self, name: &str) -> bool {
+        self.flag(name).is_some()
+    }
+
+    fn flag(&self, name: &str) -> Option<bool> {
+        match name {
+            "hidden" => Some(self.hidden),
+            "reverse" => Some(self.reverse),
+            "dirsfirst" => Some(self.dirs_first),
+            "desktopnames" => Some(self.desktop_names),
+            "du" => Some(self.du),
+            "apparent" => Some(self.apparent),
+            "dedup" => Some(self.dedup),
+            _ => None
+        }
+    }
+
+    fn set_flag(&mut self, name: &str, fun: impl FnOnce(bool) -> bool) -> io::Result<()> {
+        let flag = match name {
+            "hidden" => &mut self.hidden,
+            "reverse" => &mut self.reverse,
+            "dirsfirst" => &mut self.dirs_first,
+            "desktopnames" => &mut self.desktop_names,
+            "du" => &mut self.du,
+            "apparent" => &mut self.apparent,
+            "dedup" => &mut self.dedup,
+            _ => return Err(unknown_option(name))
+        };
+        *flag = fun(*flag);
+
+        Ok(())
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            hidden: false,
+            sort: SortKey::Name,
+            reverse: false,
+            dirs_first: true,
+            desktop_names: true,
+            exec: ExecPolicy::Trusted,
+            trusted: vec![],
+            du: false,
+            apparent: false,
+            dedup: true,
+            filter_mode: FilterMode::Auto,
+            tree_depth: 3,
+            ignore: vec![],
+        }
+    }
+}
+
+impl Display for Settings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let options: Vec<String> = OPTIONS.iter()
+            .filter_map(|option| self.get(option).ok())
+            .collect();
+        write!(f, "{}", options.join(" "))
+    }
+}
+
+impl Display for ExecPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let output = match self {
+            ExecPolicy::Ask => "ask",
+            ExecPolicy::Trusted => "trusted",
+            ExecPolicy::Never => "never",
+        };
+        write!(f, "{}", output)
+    }
+}
+
+impl Display for FilterMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let output = match self {
+            FilterMode::Auto => "auto",
+            FilterMode::Substring => "substring",
+            FilterMode::Glob => "glob",
+            FilterMode::Fuzzy => "fuzzy",
+        };
+        write!(f, "{}", output)
+    }
+}
+
+impl Display for SortKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let output = match self {
+            SortKey::Name => "name",
+            SortKey::Size => "size",
+            SortKey::Mtime => "mtime",
+            SortKey::Ext => "ext",
+        };
+        write!(f, "{}", output)
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" | "on" | "yes" | "1" => Some(true),
+        "false" | "off" | "no" | "0" => Some(false),
+        _ => None
+    }
+}
+
+fn unknown_option(name: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("unknown option: {}", name))
+}
+
+fn invalid_value(name: &str, value: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid value for {}: {}", name, value)
+    )
+}
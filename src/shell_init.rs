@@ -0,0 +1,107 @@
+use std::io;
+
+/// Shells `--init` can generate a wrapper for.
+pub const SHELLS: &[&str] = &["bash", "zsh", "fish", "nushell"];
+
+/// Placeholder for the path of the fap binary in the templates below.
+const EXE: &str = "@FAP@";
+
+// fap writes the chosen directory to a temporary file, which is left
+// empty when it is cancelled. Reading it back keeps names with spaces or
+// trailing newlines intact, which command substitution would not.
+const POSIX: &str = r#"fap() {
+    local tmp dir
+    tmp="$(mktemp -t fap.XXXXXX)" || return
+    if @FAP@ --cwd-file "$tmp" "$@" && [ -s "$tmp" ]; then
+        dir="$(cat -- "$tmp"; printf x)"
+        cd -- "${dir%x}"
+    fi
+    rm -f -- "$tmp"
+}
+"#;
+
+const BASH_BIND: &str = r#"bind -x '"\C-o": fap'
+"#;
+
+const ZSH_BIND: &str = r#"__fap_widget() {
+    fap </dev/tty
+    zle reset-prompt
+}
+zle -N __fap_widget
+bindkey '^O' __fap_widget
+"#;
+
+const FISH: &str = r#"function fap
+    set -l tmp (mktemp -t fap.XXXXXX); or return
+    if @FAP@ --cwd-file $tmp $argv; and test -s $tmp
+        cd -- (cat -- $tmp | string collect --no-trim-newlines)
+    end
+    rm -f -- $tmp
+end
+"#;
+
+const FISH_BIND: &str = r#"bind \co 'fap; commandline -f repaint'
+bind -M insert \co 'fap; commandline -f repaint'
+"#;
+
+const NUSHELL: &str = r#"def --env fap [...args] {
+    let tmp = (mktemp -t fap.XXXXXX)
+    do --ignore-errors { run-external @FAP@ --cwd-file $tmp ...$args }
+    let dir = (open --raw $tmp)
+    rm -f $tmp
+    if $dir != '' {
+        cd $dir
+    }
+}
+"#;
+
+const NUSHELL_BIND: &str = r#"$env.config.keybindings = ($env.config.keybindings | append {
+    name: fap
+    modifier: control
+    keycode: char_o
+    mode: [emacs vi_normal vi_insert]
+    event: { send: executehostcommand cmd: "fap" }
+})
+"#;
+
+/// Returns the wrapper for `shell`, which runs fap and then changes to the
+/// directory it printed. With `bind`, Ctrl-o also launches it.
+pub fn script(shell: &str, bind: bool) -> io::Result<String> {
+    let exe = std::env::current_exe()?;
+    let exe = exe.to_str().ok_or(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "the path of fap is not valid UTF-8"
+    ))?;
+
+    let (template, binding, exe) = match shell {
+        "bash" => (POSIX, BASH_BIND, posix_quote(exe)),
+        "zsh" => (POSIX, ZSH_BIND, posix_quote(exe)),
+        "fish" => (FISH, FISH_BIND, fish_quote(exe)),
+        "nu" | "nushell" => (NUSHELL, NUSHELL_BIND, nushell_quote(exe)),
+        _ => return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown shell: {} (expected one of {})", shell, SHELLS.join(", "))
+        ))
+    };
+
+    let mut output = template.replace(EXE, &exe);
+    if bind {
+        output += binding;
+    }
+    Ok(output)
+}
+
+pub fn posix_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+fn fish_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+/// Raw strings cannot contain their own delimiter, so enough `#`s are
+/// used to never match one inside `text`.
+fn nushell_quote(text: &str) -> String {
+    let hashes = "#".repeat(text.matches('#').count() + 1);
+    format!("r{hashes}'{text}'{hashes}")
+}
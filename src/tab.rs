@@ -0,0 +1,85 @@
+use std::{collections::BTreeSet, mem, path::{Path, PathBuf}, rc::Rc};
+
+use crate::{
+    app::{App, CommandState}, entry::{Entry, Line}, loader::Loader, tree::Tree, Position, START_X, START_Y
+};
+
+/// The state of a directory view that is not currently shown. The view on
+/// screen always lives in `App` itself, so switching tabs swaps these
+/// fields in and out of the `App`.
+pub struct Tab {
+    pub buffer: Vec<Line>,
+    pub entries: Vec<Rc<Entry>>,
+    pub loader: Option<Loader>,
+    pub tree: Tree,
+    pub flat: Option<usize>,
+    pub cd: PathBuf,
+    pub index: usize,
+    pub stored_position: Position,
+    pub cursor_position: Position,
+    pub command_state: CommandState,
+    pub selected: BTreeSet<PathBuf>,
+}
+
+/// The pane without focus in commander mode. Each pane keeps its own tabs.
+pub struct Pane {
+    pub view: Tab,
+    pub tabs: Vec<Tab>,
+    pub active_tab: usize,
+}
+
+impl Tab {
+    pub fn new(cd: PathBuf) -> Tab {
+        Tab {
+            buffer: vec![],
+            entries: vec![],
+            loader: None,
+            tree: Tree::default(),
+            flat: None,
+            cd,
+            index: 0,
+            stored_position: Position::new(START_X, START_Y),
+            cursor_position: Position::new(START_X, START_Y),
+            command_state: CommandState::default(),
+            selected: BTreeSet::new(),
+        }
+    }
+
+    /// Exchanges the view stored in this tab with the one shown by `app`.
+    pub fn swap(&mut self, app: &mut App) {
+        mem::swap(&mut self.buffer, &mut app.buffer);
+        mem::swap(&mut self.entries, &mut app.entries);
+        mem::swap(&mut self.loader, &mut app.loader);
+        mem::swap(&mut self.tree, &mut app.tree);
+        mem::swap(&mut self.flat, &mut app.flat);
+        mem::swap(&mut self.cd, &mut app.cd);
+        mem::swap(&mut self.index, &mut app.index);
+        mem::swap(&mut self.stored_position, &mut app.stored_position);
+        mem::swap(&mut self.cursor_position, &mut app.cursor_position);
+        mem::swap(&mut self.command_state, &mut app.command_state);
+        mem::swap(&mut self.selected, &mut app.selected);
+    }
+
+    pub fn title(cd: &Path) -> String {
+        cd.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| cd.display().to_string())
+    }
+}
+
+impl Pane {
+    pub fn new(cd: PathBuf) -> Pane {
+        Pane {
+            view: Tab::new(cd),
+            tabs: vec![Tab::new(PathBuf::new())],
+            active_tab: 0,
+        }
+    }
+
+    /// Exchanges this pane, tabs included, with the one shown by `app`.
+    pub fn swap(&mut self, app: &mut App) {
+        self.view.swap(app);
+        mem::swap(&mut self.tabs, &mut app.tabs);
+        mem::swap(&mut self.active_tab, &mut app.active_tab);
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    cmp::Ordering, collections::{BTreeSet, HashMap}, io, path::{Path, PathBuf}, rc::Rc, sync::Arc
+};
+
+use crate::{entry::{Entry, Line}, vfs::Vfs};
+
+/// The directories of a view that are expanded in place, and what was read
+/// from them. A directory is read the first time it is shown expanded, and
+/// again once the view is reloaded.
+#[derive(Default)]
+pub struct Tree {
+    /// Directories stay expanded while their parent is collapsed, so that
+    /// expanding the parent again brings them back the way they were.
+    expanded: BTreeSet<PathBuf>,
+    listings: HashMap<PathBuf, Vec<Rc<Entry>>>,
+}
+
+/// An entry that is listed, along with the ones listed under it.
+struct Branch<'a> {
+    entry: &'a Rc<Entry>,
+    children: Vec<Branch<'a>>,
+}
+
+impl Tree {
+    pub fn is_expanded(&self, dir: &Path) -> bool {
+        self.expanded.contains(dir)
+    }
+
+    pub fn expand(&mut self, dir: PathBuf) {
+        self.expanded.insert(dir);
+    }
+
+    /// Returns whether `dir` was expanded.
+    pub fn collapse(&mut self, dir: &Path) -> bool {
+        self.expanded.remove(dir)
+    }
+
+    /// Collapses every directory under `root` that is more than `depth`
+    /// levels down, so that with a `depth` of 0 all of them are.
+    pub fn collapse_below(&mut self, root: &Path, depth: usize) {
+        self.expanded.retain(|dir| match dir.strip_prefix(root) {
+            Ok(relative) => (1..=depth).contains(&relative.components().count()),
+            Err(_) => true
+        });
+    }
+
+    /// The directories under `root` that are expanded and shown, i.e. whose
+    /// parents up to `root` are all expanded as well.
+    pub fn shown(&self, root: &Path) -> Vec<PathBuf> {
+        self.expanded.iter()
+            .filter(|dir| dir.starts_with(root) && dir.as_path() != root)
+            .filter(|dir| dir.ancestors()
+                .skip(1)
+                .take_while(|ancestor| *ancestor != root)
+                .all(|ancestor| self.expanded.contains(ancestor)))
+            .cloned()
+            .collect()
+    }
+
+    /// What was read from `dir`, once it was.
+    pub fn children(&self, dir: &Path) -> Option<&[Rc<Entry>]> {
+        self.listings.get(dir).map(Vec::as_slice)
+    }
+
+    /// Reads `dir`, unless it already was. A directory that cannot be read
+    /// is shown empty, and not tried again until the view is reloaded.
+    pub fn read(&mut self, vfs: &Arc<dyn Vfs>, dir: &Path, with_metadata: bool) -> io::Result<()> {
+        if self.listings.contains_key(dir) {
+            return Ok(());
+        }
+        self.listings.insert(dir.to_path_buf(), vec![]);
+
+        let mut entries = vec![];
+        for dir_entry in vfs.list(dir)? {
+            let entry = Entry::new(dir_entry?, vfs.clone());
+            entries.push(Rc::new(if with_metadata { entry.with_metadata() } else { entry }));
+        }
+        self.listings.insert(dir.to_path_buf(), entries);
+
+        Ok(())
+    }
+
+    /// Forgets what was read, so that expanded directories are read again
+    /// the next time they are shown.
+    pub fn forget(&mut self) {
+        self.listings.clear();
+    }
+
+    /// The lines of `entries` and, under each expanded directory, of its
+    /// own entries, indented with tree guides. Only entries that are
+    /// `listed` are, and of those the ones that `match`, or that have
+    /// entries under them that do, so that a filter finds entries at any
+    /// depth. Each level is sorted by `order`.
+    pub fn lines(
+        &self,
+        entries: &[Rc<Entry>],
+        listed: &dyn Fn(&Entry) -> bool,
+        matches: &dyn Fn(&Entry) -> bool,
+        order: &dyn Fn(&Entry, &Entry) -> Ordering
+    ) -> Vec<Line> {
+        let mut lines = vec![];
+        push_lines(&mut lines, self.branches(entries, listed, matches, order), "", true);
+        lines
+    }
+
+    fn branches<'a>(
+        &'a self,
+        entries: &'a [Rc<Entry>],
+        listed: &dyn Fn(&Entry) -> bool,
+        matches: &dyn Fn(&Entry) -> bool,
+        order: &dyn Fn(&Entry, &Entry) -> Ordering
+    ) -> Vec<Branch<'a>> {
+        let mut branches: Vec<Branch> = entries.iter()
+            .filter(|entry| listed(entry))
+            .filter_map(|entry| {
+                let children = match self.listings.get(&entry.path) {
+                    Some(children) if self.expanded.contains(&entry.path) => {
+                        self.branches(children, listed, matches, order)
+                    },
+                    _ => vec![]
+                };
+                (matches(entry) || !children.is_empty()).then_some(Branch { entry, children })
+            })
+            .collect();
+        branches.sort_by(|a, b| order(a.entry, b.entry));
+        branches
+    }
+}
+
+/// Pushes the lines of `branches`, each one starting with `indent` and a
+/// guide to its parent, unless they are at the `top`.
+fn push_lines(lines: &mut Vec<Line>, branches: Vec<Branch>, indent: &str, top: bool) {
+    let count = branches.len();
+    for (i, branch) in branches.into_iter().enumerate() {
+        let (guide, children_indent) = match (top, i + 1 == count) {
+            (true, _) => (String::new(), String::new()),
+            (false, false) => (format!("{}├─ ", indent), format!("{}│  ", indent)),
+            (false, true) => (format!("{}└─ ", indent), format!("{}   ", indent)),
+        };
+        lines.push(Line::Entry { entry: branch.entry.clone(), guide });
+        push_lines(lines, branch.children, &children_indent, false);
+    }
+}
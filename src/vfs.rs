@@ -0,0 +1,182 @@
+use std::{fs, io::{self, Read}, path::{Path, PathBuf}, time::SystemTime};
+
+use crate::file_ops;
+
+/// The entries of a directory, read as the iterator is advanced.
+pub type Listing = Box<dyn Iterator<Item = io::Result<DirEntry>> + Send>;
+
+/// Where directories are listed and files read and changed. The app goes
+/// through this rather than `std::fs`, so that other sources, such as
+/// archives, can be browsed the same way as the local filesystem.
+pub trait Vfs: Send + Sync {
+    /// The entries right inside `dir`, in no particular order. Backends
+    /// that can read lazily should, so that huge directories are shown
+    /// while they are still being read.
+    fn list(&self, dir: &Path) -> io::Result<Listing>;
+
+    /// Follows symlinks.
+    fn stat(&self, path: &Path) -> io::Result<Stat>;
+
+    /// Same as `stat`, about a symlink itself rather than where it leads.
+    /// Backends without symlinks can leave it as is.
+    fn symlink_stat(&self, path: &Path) -> io::Result<Stat> {
+        self.stat(path)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>>;
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![];
+        self.open(path)?.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Fails with `ErrorKind::CrossesDevices` when `to` is somewhere that
+    /// `from` cannot simply be renamed to.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Copies a file or a whole directory tree to `to`, which must not exist.
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Removes a file or a whole directory tree.
+    fn remove(&self, path: &Path) -> io::Result<()>;
+
+    /// Creates the directory at `path`, and any missing parents.
+    fn create_dir(&self, path: &Path) -> io::Result<()>;
+}
+
+pub struct DirEntry {
+    pub path: PathBuf,
+    /// What the entry is, without following symlinks, when the backend
+    /// knows that without a `stat`.
+    pub kind: Option<Kind>,
+    /// Set by backends that read the metadata along with the listing.
+    pub stat: Option<Stat>,
+}
+
+impl DirEntry {
+    /// Follows symlinks, which takes a `stat`.
+    pub fn is_dir(&self, vfs: &dyn Vfs) -> bool {
+        match self.kind {
+            Some(Kind::Dir) => true,
+            Some(Kind::File) => false,
+            _ => match &self.stat {
+                Some(stat) => stat.is_dir,
+                None => vfs.stat(&self.path).is_ok_and(|stat| stat.is_dir)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+    File,
+    Dir,
+    Symlink,
+}
+
+/// The parts of the metadata that fap uses.
+#[derive(Clone)]
+pub struct Stat {
+    pub is_dir: bool,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+    /// Whether it is a file that can be run.
+    pub executable: bool,
+    /// Space taken by the blocks allocated on disk.
+    pub disk_len: u64,
+    /// How many names the file has, counting hard links.
+    pub links: u64,
+    /// Tells files apart whatever name they are reached by, e.g. by device
+    /// and inode, for backends that have anything of the sort.
+    pub id: Option<(u64, u64)>,
+}
+
+impl From<fs::Metadata> for Stat {
+    fn from(metadata: fs::Metadata) -> Stat {
+        #[cfg(unix)]
+        let executable = metadata.is_file()
+            && std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o111 != 0;
+        #[cfg(not(unix))]
+        let executable = false;
+
+        #[cfg(unix)]
+        let (disk_len, links, id) = {
+            use std::os::unix::fs::MetadataExt;
+            (metadata.blocks() * 512, metadata.nlink(), Some((metadata.dev(), metadata.ino())))
+        };
+        #[cfg(not(unix))]
+        let (disk_len, links, id) = (metadata.len(), 1, None);
+
+        Stat {
+            is_dir: metadata.is_dir(),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            executable,
+            disk_len,
+            links,
+            id,
+        }
+    }
+}
+
+impl From<fs::FileType> for Kind {
+    fn from(file_type: fs::FileType) -> Kind {
+        if file_type.is_symlink() {
+            return Kind::Symlink;
+        }
+        if file_type.is_dir() {
+            return Kind::Dir;
+        }
+        Kind::File
+    }
+}
+
+/// The filesystem of the machine fap runs on.
+pub struct Local;
+
+impl Vfs for Local {
+    fn list(&self, dir: &Path) -> io::Result<Listing> {
+        let read_dir = fs::read_dir(dir)?;
+        Ok(Box::new(read_dir.map(|dir_entry| {
+            let dir_entry = dir_entry?;
+            Ok(DirEntry {
+                path: dir_entry.path(),
+                kind: dir_entry.file_type().ok().map(Kind::from),
+                stat: None,
+            })
+        })))
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Stat> {
+        fs::metadata(path).map(Stat::from)
+    }
+
+    fn symlink_stat(&self, path: &Path) -> io::Result<Stat> {
+        fs::symlink_metadata(path).map(Stat::from)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        file_ops::copy_recursive(from, to)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        file_ops::remove(path)
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    io, path::{Path, PathBuf}, time::{Duration, Instant}
+};
+
+/// How long a directory has to stay quiet before it is reloaded.
+const SETTLE: Duration = Duration::from_millis(150);
+/// Directories that never stop changing are still reloaded this often.
+const MAX_DELAY: Duration = Duration::from_secs(1);
+
+/// Watches the directories shown on screen and reports which of them
+/// changed. Bursts of events (e.g. a build writing many files) are
+/// coalesced into a single change.
+pub struct Watcher {
+    backend: Backend,
+    pending: Vec<PathBuf>,
+    first_event: Option<Instant>,
+    last_event: Option<Instant>,
+}
+
+impl Watcher {
+    /// Fails on platforms, or filesystems, where watching is not possible.
+    pub fn new() -> io::Result<Watcher> {
+        Ok(Watcher {
+            backend: Backend::new()?,
+            pending: vec![],
+            first_event: None,
+            last_event: None,
+        })
+    }
+
+    /// Makes `dirs` the set of watched directories.
+    pub fn sync(&mut self, dirs: &[&Path]) {
+        self.backend.sync(dirs);
+    }
+
+    /// Returns the directories that changed and have since settled.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let changed = self.backend.read();
+        let now = Instant::now();
+        if !changed.is_empty() {
+            self.first_event.get_or_insert(now);
+            self.last_event = Some(now);
+        }
+        for dir in changed {
+            if !self.pending.contains(&dir) {
+                self.pending.push(dir);
+            }
+        }
+
+        let settled = match (self.first_event, self.last_event) {
+            (Some(first), Some(last)) => {
+                now - last >= SETTLE || now - first >= MAX_DELAY
+            },
+            _ => false
+        };
+        if !settled {
+            return vec![];
+        }
+
+        self.first_event = None;
+        self.last_event = None;
+        std::mem::take(&mut self.pending)
+    }
+}
+
+#[cfg(target_os = "linux")]
+struct Backend {
+    inotify: inotify::Inotify,
+    watches: Vec<(PathBuf, inotify::WatchDescriptor)>,
+    buffer: Vec<u8>,
+}
+
+#[cfg(target_os = "linux")]
+impl Backend {
+    fn new() -> io::Result<Backend> {
+        Ok(Backend {
+            inotify: inotify::Inotify::init()?,
+            watches: vec![],
+            buffer: vec![0; 4096],
+        })
+    }
+
+    fn sync(&mut self, dirs: &[&Path]) {
+        use inotify::WatchMask;
+
+        let mut watches = self.inotify.watches();
+        self.watches.retain(|(path, wd)| {
+            let keep = dirs.contains(&path.as_path());
+            if !keep {
+                let _ = watches.remove(wd.clone());
+            }
+            keep
+        });
+
+        for dir in dirs {
+            if self.watches.iter().any(|(path, _)| path == dir) {
+                continue;
+            }
+            let mask = WatchMask::CREATE | WatchMask::DELETE | WatchMask::MOVE
+                | WatchMask::CLOSE_WRITE | WatchMask::ATTRIB
+                | WatchMask::DELETE_SELF | WatchMask::MOVE_SELF;
+            // Directories that cannot be watched can still be reloaded by hand.
+            if let Ok(wd) = watches.add(dir, mask) {
+                self.watches.push((dir.to_path_buf(), wd));
+            }
+        }
+    }
+
+    fn read(&mut self) -> Vec<PathBuf> {
+        let mut changed = vec![];
+        while let Ok(events) = self.inotify.read_events(&mut self.buffer) {
+            let mut empty = true;
+            for event in events {
+                empty = false;
+                let dir = self.watches.iter().find(|(_, wd)| *wd == event.wd);
+                if let Some((dir, _)) = dir {
+                    if !changed.contains(dir) {
+                        changed.push(dir.clone());
+                    }
+                }
+            }
+            if empty {
+                break;
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+struct Backend;
+
+#[cfg(not(target_os = "linux"))]
+impl Backend {
+    fn new() -> io::Result<Backend> {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "watching directories is only supported on linux"
+        ));
+    }
+
+    fn sync(&mut self, _dirs: &[&Path]) {}
+
+    fn read(&mut self) -> Vec<PathBuf> {
+        return vec![];
+    }
+}